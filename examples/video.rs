@@ -6,16 +6,18 @@ use enigma::{
         fitness::{IoCFitness, NgramFitness},
         EnigmaAnalysisRotors,
     },
-    enigma::{Enigma, ReflectorId},
+    enigma::{stepping::Ratchet, Enigma, ReflectorId},
 };
 
+#[allow(dead_code)]
 const SINGLE: &str = include_str!("../data/single");
 const BIGRAMS: &str = include_str!("../data/bigrams");
+#[allow(dead_code)]
 const TRIGRAMS: &str = include_str!("../data/trigrams");
 const QUADGRAMS: &str = include_str!("../data/quadgrams");
 
 // For those interested, these were the original settings
-// II V III / 7 4 19 / 12 2 20 / AF TV KO BL RW
+// II V III / 8 5 20 / 13 3 21 / AF TV KO BL RW
 const CIPHER_TEXT: &str = "OZLUDYAKMGMXVFVARPMJIKVWPMBVWMOIDHYPLAYUWGBZFAFAFUQFZQISLEZMYPVBRDDLAGIHIFUJDFADORQOOMIZP\
                            YXDCBPWDSSNUSYZTJEWZPWFBWBMIEQXRFASZLOPPZRJKJSPPSTXKPUWYSKNMZZLHJDXJMMMDFODIHUBVCXMNICNYQ\
                            BNQODFQLOGPZYXRJMTLMRKQAUQJPADHDZPFIKTQBFXAYMVSZPKXIQLOQCVRPKOBZSXIUBAAJBRSNAFDMLLBVSYXIS\
@@ -31,8 +33,15 @@ fn main() {
 
     let start_time = Instant::now();
 
-    let rotor_configurations =
-        find_rotor_configurations(CIPHER_TEXT, EnigmaAnalysisRotors::Five, &[], 10, &ioc);
+    let rotor_configurations = find_rotor_configurations(
+        CIPHER_TEXT,
+        EnigmaAnalysisRotors::Five,
+        ReflectorId::B,
+        Ratchet,
+        &[],
+        10,
+        &ioc,
+    );
 
     println!("Rotor search time: {:?}", start_time.elapsed());
 
@@ -46,8 +55,13 @@ fn main() {
     println!("Current decryption: {}\n", output);
 
     // Next find the best ring settings for the best configuration (index 0);
-    let rotor_and_ring_configuration =
-        find_ring_settings(CIPHER_TEXT, *rotor_configurations[0], &bigrams);
+    let rotor_and_ring_configuration = find_ring_settings(
+        CIPHER_TEXT,
+        *rotor_configurations[0],
+        ReflectorId::B,
+        Ratchet,
+        &bigrams,
+    );
 
     println!("{}", *rotor_and_ring_configuration);
 
@@ -56,8 +70,14 @@ fn main() {
     println!("Current decryption: {}\n", output);
 
     // Finally, perform hill climbing to find plugs one at a time.
-    let optimal_key_with_plugs =
-        find_plugs(CIPHER_TEXT, *rotor_and_ring_configuration, 10, &quadgrams);
+    let optimal_key_with_plugs = find_plugs(
+        CIPHER_TEXT,
+        *rotor_and_ring_configuration,
+        ReflectorId::B,
+        Ratchet,
+        10,
+        &quadgrams,
+    );
     println!("{}", *optimal_key_with_plugs);
 
     let mut enigma = Enigma::new(*optimal_key_with_plugs, ReflectorId::B);
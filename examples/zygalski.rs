@@ -0,0 +1,140 @@
+use std::time::Instant;
+
+use enigma::{
+    analysis::{
+        find_rotor_configurations,
+        fitness::IoCFitness,
+        zygalski::{Female, Sheets},
+        EnigmaAnalysisRotors,
+    },
+    enigma::{stepping::Ratchet, EnigmaKey, ReflectorId, RotorId},
+    procedure::encrypt_1938,
+};
+
+// The day's key, which the operators share. Each message is sent at a Grundstellung of the
+// operator's choosing, with their own message key doubled after it.
+const DAY_KEY: &str = "II V III / 1 1 1 / 8 5 20 / AF TV KO BL RW";
+const MESSAGES: usize = 300;
+
+const PLAINTEXT: &str = "IPROPOSETOCONSIDERTHEQUESTIONCANMACHINESTHINKTHISSHOULDBEGINWITHDEFINITIONSOFTHEMEANING\
+                         OFTHETERMSMACHINEANDTHINKTHEDEFINITIONSMIGHTBEFRAMEDSOASTOREFLECTSOFARASPOSSIBLETHENORMAL\
+                         USEOFTHEWORDSBUTTHISATTITUDEISDANGEROUSIFTHEMEANINGOFTHEWORDSMACHINEANDTHINKARETOBEFOUND\
+                         BYEXAMININGHOWTHEYARECOMMONLYUSEDITISDIFFICULTTOESCAPETHECONCLUSIONTHATTHEMEANING";
+
+fn main() {
+    let key: EnigmaKey = DAY_KEY.parse().unwrap();
+
+    // A small generator, so each run intercepts the same traffic.
+    let mut seed = 1938_u64;
+    let mut letters = |n: usize| -> String {
+        (0..n)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                ((seed >> 33) % 26) as u8 + b'A'
+            })
+            .map(char::from)
+            .collect()
+    };
+    let messages: Vec<_> = (0..MESSAGES)
+        .map(|_| {
+            let grundstellung = letters(3);
+            let message_key = letters(3);
+            encrypt_1938(
+                &key,
+                ReflectorId::B,
+                Ratchet,
+                &grundstellung,
+                &message_key,
+                PLAINTEXT,
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let indicators: Vec<_> = messages.iter().map(|m| m.indicator().clone()).collect();
+    let females = Female::find(&indicators).unwrap();
+    println!(
+        "{} messages intercepted, with {} females",
+        messages.len(),
+        females.len()
+    );
+
+    let start_time = Instant::now();
+    let sheets = Sheets::new(EnigmaAnalysisRotors::Five, ReflectorId::B);
+    println!("Made {} sheets in {:?}", sheets.len(), start_time.elapsed());
+
+    let sheet = sheets
+        .get([RotorId::II, RotorId::V, RotorId::III], 0)
+        .unwrap();
+    println!(
+        "\nThe sheet for II V III with the left rotor at A has {} holes:\n{}\n",
+        sheet.hole_count(),
+        sheet
+    );
+
+    let start_time = Instant::now();
+    let candidates = sheets.stack(&females);
+    println!(
+        "Stacking left {} candidates in {:?}:",
+        candidates.len(),
+        start_time.elapsed()
+    );
+    for candidate in candidates.iter().take(20) {
+        let rings: String = candidate
+            .ring_settings()
+            .iter()
+            .map(|&r| (r + b'A') as char)
+            .collect();
+        let [left, middle, right] = candidate.rotors();
+        println!(
+            "{} {} {} rings {} from {} females",
+            left,
+            middle,
+            right,
+            rings,
+            candidate.females()
+        );
+    }
+    if candidates.len() > 20 {
+        println!("...");
+    }
+
+    let found = candidates.iter().any(|candidate| {
+        candidate.rotors()
+            == &[
+                *key.left_rotor().id(),
+                *key.middle_rotor().id(),
+                *key.right_rotor().id(),
+            ]
+            && candidate.ring_settings()
+                == &[
+                    key.left_rotor().ring_setting(),
+                    key.middle_rotor().ring_setting(),
+                    key.right_rotor().ring_setting(),
+                ]
+    });
+    println!(
+        "The day's rotor order and ring settings survived: {}",
+        found
+    );
+
+    // The IoC search only has one message to work with, and can't tell the ring settings from the
+    // starting position.
+    let start_time = Instant::now();
+    let rotor_configurations = find_rotor_configurations(
+        messages[0].text(),
+        EnigmaAnalysisRotors::Five,
+        ReflectorId::B,
+        Ratchet,
+        &[],
+        5,
+        &IoCFitness::new(),
+    );
+    println!("\nIoC search time: {:?}", start_time.elapsed());
+    println!("Top 5 rotor configurations for the first message:");
+    for key in &rotor_configurations {
+        println!("{}", **key);
+    }
+}
@@ -1,19 +1,72 @@
 pub mod fitness;
+pub mod rejewski;
+pub mod zygalski;
 
-use std::ops::Deref;
+use std::{array, ops::Deref};
 
 use itertools::iproduct;
 use rayon::prelude::*;
 
-use crate::enigma::{Enigma, EnigmaKey, Plugboard, ReflectorId, Rotor, RotorId};
+use crate::enigma::{
+    batch::{BatchTables, TableBatch},
+    permutation::Permutation,
+    scrambler::ScramblerTables,
+    stepping::Stepping,
+    to_letters,
+    ukwd::UkwD,
+    Enigma, EnigmaKey, Plugboard, ReflectorId, Rotor, RotorId,
+};
 use fitness::FitnessFunction;
 
+/// How many keys `find_rotor_configurations` runs through a `TableBatch` at once. Four was the
+/// fastest measured, but any handful of lanes is within about 15%, and
+/// `examples/batch_throughput.rs` measures each on the machine it's run on.
+pub const DEFAULT_LANES: usize = 4;
+
 pub enum EnigmaAnalysisRotors {
     Three,
     Five,
     Eight,
+    Custom(Vec<RotorId>),
 }
 
+impl EnigmaAnalysisRotors {
+    fn rotor_ids(&self) -> &[RotorId] {
+        match self {
+            EnigmaAnalysisRotors::Three => &[RotorId::I, RotorId::II, RotorId::III],
+            EnigmaAnalysisRotors::Five => &[
+                RotorId::I,
+                RotorId::II,
+                RotorId::III,
+                RotorId::IV,
+                RotorId::V,
+            ],
+            EnigmaAnalysisRotors::Eight => &[
+                RotorId::I,
+                RotorId::II,
+                RotorId::III,
+                RotorId::IV,
+                RotorId::V,
+                RotorId::VI,
+                RotorId::VII,
+                RotorId::VIII,
+            ],
+            EnigmaAnalysisRotors::Custom(rotors) => rotors,
+        }
+    }
+
+    /// Every order of three different rotors, from left to right.
+    fn rotor_orders(&self) -> Vec<[RotorId; 3]> {
+        let available_rotors = self.rotor_ids();
+        // Collecting ends up being faster as the parallel iterator doesn't need to syncronise access.
+        iproduct!(available_rotors, available_rotors, available_rotors)
+            .map(|(a, b, c)| [*a, *b, *c])
+            .filter(|[a, b, c]| a != b && a != c && b != c)
+            .collect()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ScoredEnigmaKey {
     key: EnigmaKey,
     score: f32,
@@ -45,69 +98,95 @@ impl ScoredEnigmaKey {
     }
 }
 
-pub fn find_rotor_configurations(
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScoredUkwD {
+    ukwd: UkwD,
+    score: f32,
+}
+
+impl Deref for ScoredUkwD {
+    type Target = UkwD;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ukwd
+    }
+}
+
+impl ScoredUkwD {
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+}
+
+pub fn find_rotor_configurations<S: Stepping + Copy + Sync>(
     cipher: &str,
     rotors: EnigmaAnalysisRotors,
+    reflector: ReflectorId,
+    stepping: S,
     plugboard: &[(char, char)],
     required_keys: usize,
     f: &(impl FitnessFunction + Sync),
 ) -> Vec<ScoredEnigmaKey> {
-    let available_rotors: &[RotorId] = match rotors {
-        EnigmaAnalysisRotors::Three => &[RotorId::I, RotorId::II, RotorId::III],
-        EnigmaAnalysisRotors::Five => &[
-            RotorId::I,
-            RotorId::II,
-            RotorId::III,
-            RotorId::IV,
-            RotorId::V,
-        ],
-        EnigmaAnalysisRotors::Eight => &[
-            RotorId::I,
-            RotorId::II,
-            RotorId::III,
-            RotorId::IV,
-            RotorId::V,
-            RotorId::VI,
-            RotorId::VII,
-            RotorId::VIII,
-        ],
-    };
+    find_rotor_configurations_with_lanes::<DEFAULT_LANES, S>(
+        cipher,
+        rotors,
+        reflector,
+        stepping,
+        plugboard,
+        required_keys,
+        f,
+    )
+}
 
+/// Like `find_rotor_configurations`, but tries `LANES` keys at once rather than `DEFAULT_LANES`.
+/// `LANES` must not be zero.
+pub fn find_rotor_configurations_with_lanes<const LANES: usize, S: Stepping + Copy + Sync>(
+    cipher: &str,
+    rotors: EnigmaAnalysisRotors,
+    reflector: ReflectorId,
+    stepping: S,
+    plugboard: &[(char, char)],
+    required_keys: usize,
+    f: &(impl FitnessFunction + Sync),
+) -> Vec<ScoredEnigmaKey> {
     let plugboard = Plugboard::new(plugboard);
-
-    // Collecting ends up being faster as the parallel iterator doesn't need to syncronise access.
-    let rotors: Vec<_> = iproduct!(available_rotors, available_rotors, available_rotors)
-        .map(|(a, b, c)| (*a, *b, *c))
-        .filter(|(a, b, c)| a != b && a != c && b != c)
-        .collect();
+    let cipher = &cipher_letters(cipher);
 
     let mut key_set: Vec<ScoredEnigmaKey> = rotors
+        .rotor_orders()
         .into_par_iter() // more cores more better!
-        .filter_map(|(a, b, c)| {
-            println!("{:?} {:?} {:?}", a, b, c);
-
+        .filter_map(|[a, b, c]| {
             let mut max_fitness: f32 = -1e30;
             let mut best_key = None::<EnigmaKey>;
 
-            const RANGE: std::ops::Range<u8> = 0..26;
-            let mut buf = String::with_capacity(cipher.len());
-            iproduct!(RANGE, RANGE, RANGE).for_each(|(i, j, k)| {
-                let left_rotor = Rotor::new(a, i, 0);
-                let middle_rotor = Rotor::new(b, j, 0);
-                let right_rotor = Rotor::new(c, k, 0);
-                let key = EnigmaKey::new(left_rotor, middle_rotor, right_rotor, plugboard);
+            let key = EnigmaKey::new(
+                Rotor::new(a, 0, 0),
+                Rotor::new(b, 0, 0),
+                Rotor::new(c, 0, 0),
+                plugboard,
+            );
+            let tables = BatchTables::new(&key, reflector, stepping);
 
-                let mut e = Enigma::new(key, ReflectorId::B);
-
-                buf.clear();
-                buf.extend(cipher.chars().map(|c| e.encrypt(c)));
-
-                let fitness = f.score(&buf);
-                if fitness > max_fitness {
-                    max_fitness = fitness;
-                    best_key = Some(key);
+            const RANGE: std::ops::Range<u8> = 0..26;
+            let positions: Vec<_> = iproduct!(RANGE, RANGE, RANGE)
+                .map(|(i, j, k)| [i, j, k])
+                .collect();
+            let mut outputs = array::from_fn(|_| Vec::with_capacity(cipher.len()));
+            for chunk in positions.chunks(LANES) {
+                // The last chunk is padded out by repeating its first key, and the extra lanes ignored.
+                let lanes: [_; LANES] =
+                    array::from_fn(|lane| *chunk.get(lane).unwrap_or(&chunk[0]));
+                let mut batch = TableBatch::new(&tables, &lanes);
+                let keys = batch.keys();
+
+                let scores = score_batch(&mut batch, cipher, &mut outputs, f);
+                for (&key, &fitness) in keys.iter().zip(&scores).take(chunk.len()) {
+                    if fitness > max_fitness {
+                        max_fitness = fitness;
+                        best_key = Some(key);
+                    }
                 }
-            });
+            }
 
             best_key.map(|key| ScoredEnigmaKey {
                 key,
@@ -121,13 +200,31 @@ pub fn find_rotor_configurations(
     key_set
 }
 
-pub fn find_ring_settings(
+/// Encrypts the letters in every lane of the batch and scores each lane's output. The outputs are
+/// only kept so their allocations can be reused from one batch to the next.
+fn score_batch<const L: usize>(
+    batch: &mut TableBatch<L>,
+    letters: &[u8],
+    outputs: &mut [Vec<u8>; L],
+    f: &impl FitnessFunction,
+) -> [f32; L] {
+    outputs.iter_mut().for_each(Vec::clear);
+    batch.encrypt_into(letters, outputs).unwrap();
+
+    array::from_fn(|lane| f.score_letters(&outputs[lane]))
+}
+
+pub fn find_ring_settings<S: Stepping + Copy + Sync>(
     cipher: &str,
     mut key: EnigmaKey,
+    reflector: ReflectorId,
+    stepping: S,
     f: &(impl FitnessFunction + Sync),
 ) -> ScoredEnigmaKey {
+    let cipher = &cipher_letters(cipher);
+
     for &rotor_idx in &[EnigmaKey::right_rotor_mut, EnigmaKey::middle_rotor_mut] {
-        let optimal_index = find_ring_setting(key, cipher, rotor_idx, f);
+        let optimal_index = find_ring_setting(key, reflector, stepping, cipher, rotor_idx, f);
         let rotor = rotor_idx(&mut key);
         rotor.set_ring_setting(optimal_index);
         let rotor_pos = rotor.rotor_position();
@@ -135,23 +232,23 @@ pub fn find_ring_settings(
     }
 
     // Calculate fitness and return scored key.
-    let mut enigma = Enigma::new(key, ReflectorId::B);
-    let decription: String = cipher.chars().map(|c| enigma.encrypt(c)).collect();
     ScoredEnigmaKey {
         key,
-        score: f.score(&decription),
+        score: score_key(key, reflector, stepping, cipher, f),
     }
 }
 
-fn find_ring_setting(
+fn find_ring_setting<S: Stepping + Copy + Sync>(
     mut key: EnigmaKey,
-    cipher: &str,
+    reflector: ReflectorId,
+    stepping: S,
+    cipher: &[u8],
     rotor_idx: fn(&mut EnigmaKey) -> &mut Rotor,
     f: &(impl FitnessFunction + Sync),
 ) -> u8 {
     let mut optimal_ring_setting = 0;
     let mut max_fitness = -1e30;
-    let mut buf = String::with_capacity(cipher.len());
+    let mut buf = Vec::with_capacity(cipher.len());
 
     let start_pos = rotor_idx(&mut key).rotor_position();
 
@@ -160,11 +257,11 @@ fn find_ring_setting(
         cur_rotor.set_rotor_position((start_pos + i) % 26);
         cur_rotor.set_ring_setting(i);
 
-        let mut enigma = Enigma::new(key, ReflectorId::B);
+        let mut enigma = Enigma::with_stepping(key, reflector, stepping);
 
         buf.clear();
-        buf.extend(cipher.chars().map(|c| enigma.encrypt(c)));
-        let fitness = f.score(&buf);
+        enigma.encrypt_into(cipher, &mut buf).unwrap();
+        let fitness = f.score_letters(&buf);
 
         if fitness > max_fitness {
             max_fitness = fitness;
@@ -175,21 +272,27 @@ fn find_ring_setting(
     optimal_ring_setting
 }
 
-pub fn find_plugs(
+pub fn find_plugs<S: Stepping + Copy + Sync>(
     cipher: &str,
     mut key: EnigmaKey,
+    reflector: ReflectorId,
+    stepping: S,
     max_plugs: u8,
     f: &(impl FitnessFunction + Sync),
 ) -> ScoredEnigmaKey {
+    let cipher = &cipher_letters(cipher);
     let mut plugs = Vec::with_capacity(5);
 
+    // Only the plugboard changes from here on, so the rotors only need running once.
+    let tables = Enigma::with_stepping(key, reflector, stepping).scrambler_tables(cipher.len());
+
     // We're looking for *up to* max_plugs, we don't have to *have* max_plugs.
     let mut max_fitness = -1e30;
     let mut best_key = key;
 
     for _ in 0..max_plugs {
         key.set_plugboard(Plugboard::new(&plugs));
-        let (fitness, next_plug) = find_plug(key, cipher, f);
+        let (fitness, next_plug) = find_plug(key.plugboard(), &tables, cipher, f);
         plugs.push(next_plug);
 
         // The next best plug would make it worse, so stop.
@@ -201,25 +304,29 @@ pub fn find_plugs(
         best_key.set_plugboard(Plugboard::new(&plugs));
     }
 
-    let mut enigma = Enigma::new(best_key, ReflectorId::B);
-    let decryption: String = cipher.chars().map(|c| enigma.encrypt(c)).collect();
+    let mut decryption = Vec::with_capacity(cipher.len());
+    tables
+        .encrypt_into(best_key.plugboard(), cipher, &mut decryption)
+        .unwrap();
+
     ScoredEnigmaKey {
         key: best_key,
-        score: f.score(&decryption),
+        score: f.score_letters(&decryption),
     }
 }
 
 fn find_plug(
-    mut key: EnigmaKey,
-    cipher: &str,
+    plugboard: &Plugboard,
+    tables: &ScramblerTables,
+    cipher: &[u8],
     f: &(impl FitnessFunction + Sync),
 ) -> (f32, (char, char)) {
-    let unplugged = key.plugboard().unplugged();
-    let mut plugs = key.plugboard().generate_connections();
+    let unplugged = plugboard.unplugged();
+    let mut plugs = plugboard.generate_connections();
 
     let mut optimal_plug = ('A', 'A');
     let mut max_fitness = -1e30;
-    let mut buf = String::with_capacity(cipher.len());
+    let mut buf = Vec::with_capacity(cipher.len());
     for (_, i) in unplugged.iter().zip(0..).filter(|(v, _)| **v) {
         for (_, j) in unplugged
             .iter()
@@ -232,13 +339,12 @@ fn find_plug(
             let plug = (a, b);
 
             plugs.push(plug);
-            key.set_plugboard(Plugboard::new(&plugs));
+            let plugboard = Plugboard::new(&plugs);
 
-            let mut enigma = Enigma::new(key, ReflectorId::B);
             buf.clear();
-            buf.extend(cipher.chars().map(|c| enigma.encrypt(c)));
+            tables.encrypt_into(&plugboard, cipher, &mut buf).unwrap();
 
-            let fitness = f.score(&buf);
+            let fitness = f.score_letters(&buf);
             if fitness > max_fitness {
                 max_fitness = fitness;
                 optimal_plug = plug;
@@ -250,3 +356,126 @@ fn find_plug(
 
     (max_fitness, optimal_plug)
 }
+
+/// Hill climbs from the `start` wiring to recover a UKW-D. Each round tries swapping the partners of
+/// every two plug pairs, keeping the best swap until none of them improve the fitness.
+pub fn find_ukw_d<S: Stepping + Copy + Sync>(
+    cipher: &str,
+    key: EnigmaKey,
+    start: UkwD,
+    stepping: S,
+    f: &(impl FitnessFunction + Sync),
+) -> ScoredUkwD {
+    let cipher = &cipher_letters(cipher);
+    let mut pairs = start.pairs();
+
+    // The rotors don't change between candidates, only the wiring inside the reflector, so they're
+    // recorded once and each candidate only rewires the tables.
+    let tables = Enigma::with_stepping(key, start, stepping).scrambler_tables(cipher.len());
+    let mut decryption = Vec::with_capacity(cipher.len());
+    tables
+        .encrypt_into(key.plugboard(), cipher, &mut decryption)
+        .unwrap();
+    let mut max_fitness = f.score_letters(&decryption);
+
+    loop {
+        let (fitness, next_pairs) = find_ukw_d_swap(&tables, key.plugboard(), &pairs, cipher, f);
+
+        // The best swap doesn't improve things, so we're at a peak.
+        if fitness <= max_fitness {
+            break;
+        }
+
+        max_fitness = fitness;
+        pairs = next_pairs;
+    }
+
+    ScoredUkwD {
+        ukwd: UkwD::new(&pairs).unwrap(),
+        score: max_fitness,
+    }
+}
+
+fn find_ukw_d_swap(
+    tables: &ScramblerTables,
+    plugboard: &Plugboard,
+    pairs: &[(char, char)],
+    cipher: &[u8],
+    f: &(impl FitnessFunction + Sync),
+) -> (f32, Vec<(char, char)>) {
+    let mut candidate = pairs.to_vec();
+
+    let mut optimal_pairs = candidate.clone();
+    let mut max_fitness = -1e30;
+    let mut buf = Vec::with_capacity(cipher.len());
+    for i in 0..pairs.len() {
+        for j in i + 1..pairs.len() {
+            let (a, b) = pairs[i];
+            let (c, d) = pairs[j];
+
+            for &(first, second) in &[((a, c), (b, d)), ((a, d), (b, c))] {
+                candidate[i] = first;
+                candidate[j] = second;
+
+                // Swapping partners between valid pairs always gives a valid wiring.
+                let ukwd = UkwD::new(&candidate).unwrap();
+
+                buf.clear();
+                tables
+                    .encrypt_into_rewired(plugboard, ukwd, cipher, &mut buf)
+                    .unwrap();
+
+                let fitness = f.score_letters(&buf);
+                if fitness > max_fitness {
+                    max_fitness = fitness;
+                    optimal_pairs.copy_from_slice(&candidate);
+                }
+            }
+
+            // Put the original pairs back before trying the next swap.
+            candidate[i] = pairs[i];
+            candidate[j] = pairs[j];
+        }
+    }
+
+    (max_fitness, optimal_pairs)
+}
+
+/// A key with no plugs, for the indicator attacks, which work on the rotors alone.
+fn unplugged_key(rotors: [RotorId; 3], positions: [u8; 3], ring_settings: [u8; 3]) -> EnigmaKey {
+    let rotor = |i: usize| Rotor::new(rotors[i], positions[i], ring_settings[i]);
+    EnigmaKey::new(rotor(0), rotor(1), rotor(2), Plugboard::new(&[]))
+}
+
+/// The machine's permutation at each position of the right rotor, with the ring settings at A. The
+/// indicator attacks assume only the right rotor moves during an indicator, so these are all they
+/// need for each position of the other two.
+fn right_rotor_permutations(
+    rotors: [RotorId; 3],
+    left: u8,
+    middle: u8,
+    reflector: ReflectorId,
+) -> [Permutation; 26] {
+    array::from_fn(|right| {
+        let key = unplugged_key(rotors, [left, middle, right as u8], [0; 3]);
+        Enigma::new(key, reflector).permutation()
+    })
+}
+
+/// Converts the ciphertext once up front, so the searches can work on the machine's internal alphabet.
+fn cipher_letters(cipher: &str) -> Vec<u8> {
+    to_letters(cipher).unwrap_or_else(|e| panic!("Invalid cipher text: {}", e))
+}
+
+fn score_key<S: Stepping + Copy + Sync>(
+    key: EnigmaKey,
+    reflector: ReflectorId,
+    stepping: S,
+    cipher: &[u8],
+    f: &(impl FitnessFunction + Sync),
+) -> f32 {
+    let mut enigma = Enigma::with_stepping(key, reflector, stepping);
+    let mut decryption = Vec::with_capacity(cipher.len());
+    enigma.encrypt_into(cipher, &mut decryption).unwrap();
+    f.score_letters(&decryption)
+}
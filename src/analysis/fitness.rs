@@ -1,5 +1,7 @@
 use std::convert::TryInto;
 
+use crate::error::EnigmaError;
+
 const EPSILON: f32 = 3e-10;
 
 // why you still unstable!?!
@@ -8,13 +10,20 @@ impl<'a, T, const N: usize> Iterator for ArrWindows<'a, T, N> {
     type Item = &'a [T; N];
     fn next(&mut self) -> Option<Self::Item> {
         let next = self.0.get(..N)?;
-        self.0 = &self.0.get(1..)?;
+        self.0 = self.0.get(1..)?;
         next.try_into().ok()
     }
 }
 
 pub trait FitnessFunction {
     fn score(&self, text: &str) -> f32;
+
+    /// Scores letters in the machine's internal alphabet, where A is 0 and Z is 25, which must all be
+    /// in the range 0..26. This is what the searches in `analysis` call, so implementations should
+    /// override it to skip the conversion.
+    fn score_letters(&self, letters: &[u8]) -> f32 {
+        self.score(&crate::enigma::from_letters(letters))
+    }
 }
 
 // This one implements the SingleCharacterFitness, BigramFitness, TrigramFitness, and QuadgramFitness
@@ -25,23 +34,34 @@ pub struct NgramFitness<const N: usize> {
 
 impl<const N: usize> NgramFitness<N> {
     pub fn new<'a>(ngrams: impl IntoIterator<Item = &'a str>) -> Self {
+        Self::try_new(ngrams).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Each line is of the form `KEY,value`, where the key is N uppercase letters and the value is
+    /// its log10 probability.
+    pub fn try_new<'a>(ngrams: impl IntoIterator<Item = &'a str>) -> Result<Self, EnigmaError> {
         let num_ngrams = Self::index(&[b'Z'; N]) + 1;
         let mut store = vec![EPSILON.log10(); num_ngrams];
 
-        for line in ngrams {
-            let (key, value) = line.split_once(',').expect("Invalid ngram entry");
+        for (line_no, line) in ngrams.into_iter().enumerate() {
+            let invalid = || EnigmaError::InvalidNgram {
+                line: line_no,
+                entry: line.to_owned(),
+            };
+
+            let (key, value) = line.split_once(',').ok_or_else(invalid)?;
             let valid_key = key.chars().all(|c| c.is_ascii_uppercase()) && key.chars().count() == N;
             if !valid_key {
-                panic!("Invalid ngram key: {:?}", key);
+                return Err(invalid());
             }
 
             let i = Self::index(key.as_bytes()[..N].try_into().unwrap());
-            let value = value.parse().expect("Invalid ngram value");
+            let value = value.parse().map_err(|_| invalid())?;
 
             store[i] = value;
         }
 
-        Self { ngrams: store }
+        Ok(Self { ngrams: store })
     }
 
     fn index(v: &[u8; N]) -> usize {
@@ -51,6 +71,15 @@ impl<const N: usize> NgramFitness<N> {
             .map(|(s, v)| ((*v - b'A') as usize) << s)
             .sum()
     }
+
+    /// Like `index`, but for letters that are already in the range 0..26.
+    fn letter_index(v: &[u8; N]) -> usize {
+        (0..)
+            .map(|i| i * 5)
+            .zip(v.iter().rev())
+            .map(|(s, v)| (*v as usize) << s)
+            .sum()
+    }
 }
 
 impl<const N: usize> FitnessFunction for NgramFitness<N> {
@@ -65,6 +94,13 @@ impl<const N: usize> FitnessFunction for NgramFitness<N> {
             .map(|i| self.ngrams[i])
             .sum()
     }
+
+    fn score_letters(&self, letters: &[u8]) -> f32 {
+        ArrWindows(letters)
+            .map(Self::letter_index)
+            .map(|i| self.ngrams[i])
+            .sum()
+    }
 }
 
 pub struct IoCFitness {}
@@ -73,6 +109,14 @@ impl IoCFitness {
     pub fn new() -> Self {
         Self {}
     }
+
+    fn index_of_coincidence(histogram: &[u32; 26], len: usize) -> f32 {
+        // A letter that never appears would underflow v - 1, but adds nothing either way.
+        let total: u32 = histogram.iter().map(|&v| v * v.saturating_sub(1)).sum();
+
+        let n = len as f32;
+        total as f32 / (n * (n - 1.))
+    }
 }
 
 impl Default for IoCFitness {
@@ -93,10 +137,14 @@ impl FitnessFunction for IoCFitness {
             .map(|c| (c as u8 - b'A') as usize)
             .for_each(|i| histogram[i] += 1);
 
-        let total: u32 = histogram.iter().map(|&v| v * (v - 1)).sum();
+        Self::index_of_coincidence(&histogram, text.chars().count())
+    }
+
+    fn score_letters(&self, letters: &[u8]) -> f32 {
+        let mut histogram = [0_u32; 26];
+        letters.iter().for_each(|&c| histogram[c as usize] += 1);
 
-        let n = text.chars().count() as f32;
-        total as f32 / (n * (n - 1.))
+        Self::index_of_coincidence(&histogram, letters.len())
     }
 }
 
@@ -152,4 +200,28 @@ impl FitnessFunction for KnownPlainTextFitness {
             .map(|(a, b)| (a == b) as u32)
             .sum::<u32>() as _
     }
+
+    fn score_letters(&self, letters: &[u8]) -> f32 {
+        // Gaps between the known words are stored as 0, which never matches a letter.
+        self.plaintext
+            .as_bytes()
+            .iter()
+            .zip(letters)
+            .map(|(&a, &b)| (a.wrapping_sub(b'A') == b) as u32)
+            .sum::<u32>() as _
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ioc_with_missing_letters() {
+        // Most letters don't appear at all, which used to underflow when counting their pairs.
+        let f = IoCFitness::new();
+        assert_eq!(f.score("AABB"), 4. / 12.);
+        assert_eq!(f.score_letters(&[0, 0, 1, 1]), 4. / 12.);
+        assert_eq!(f.score("ABCD"), 0.);
+    }
 }
@@ -0,0 +1,378 @@
+// Rejewski's attack on the doubled indicators used before May 1940. Every message of the day had its
+// key typed twice at the same Grundstellung, so the first and fourth letters of each indicator are
+// the same letter enciphered three places apart, as are the second and fifth, and the third and
+// sixth. With enough indicators that gives the products AD, BE and CF of the machine's permutations
+// at those six places. The plugboard only relabels the letters in their cycles, so the cycle lengths
+// depend on the rotors alone, and can be looked up in a catalogue of every rotor order and position.
+
+use std::{array, collections::HashMap, convert::TryFrom, fmt::Display};
+
+use itertools::iproduct;
+use rayon::prelude::*;
+
+use crate::{
+    enigma::{
+        permutation::{CycleType, Permutation},
+        Enigma, EnigmaKey, Reflector, ReflectorId, RotorId,
+    },
+    error::EnigmaError,
+    procedure::Indicator,
+};
+
+use super::{right_rotor_permutations, unplugged_key, EnigmaAnalysisRotors};
+
+/// The products AD, BE and CF, where A to F are the machine's permutations at the six letters of a
+/// doubled indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Products {
+    ad: Permutation,
+    be: Permutation,
+    cf: Permutation,
+}
+
+impl Products {
+    /// Works out the products from a day's doubled indicators, which must all have been sent at the
+    /// same Grundstellung. AD takes the first letter of every indicator to its fourth, and so on.
+    /// Fails if an indicator disagrees with the others, or if more than one letter never turns up.
+    pub fn from_indicators(indicators: &[Indicator]) -> Result<Self, EnigmaError> {
+        let mut products = [[None; 26]; 3];
+        let mut reached = [[false; 26]; 3];
+
+        for indicator in indicators {
+            let letters = match indicator {
+                Indicator::Doubled(letters) if letters.len() == 6 => letters.as_bytes(),
+                _ => return Err(EnigmaError::InvalidIndicator(indicator.to_string())),
+            };
+            if let Some(position) = letters.iter().position(|c| !c.is_ascii_uppercase()) {
+                return Err(EnigmaError::InvalidCharacter {
+                    position,
+                    character: letters[position] as char,
+                });
+            }
+
+            for (i, (product, reached)) in products.iter_mut().zip(&mut reached).enumerate() {
+                let from = (letters[i] - b'A') as usize;
+                let to = letters[i + 3] - b'A';
+                match product[from] {
+                    Some(existing) if existing == to => {}
+                    None if !reached[to as usize] => {
+                        product[from] = Some(to);
+                        reached[to as usize] = true;
+                    }
+                    _ => return Err(EnigmaError::ConflictingIndicator(indicator.to_string())),
+                }
+            }
+        }
+
+        let [ad, be, cf] = products;
+        Ok(Self {
+            ad: complete(ad, &reached[0])?,
+            be: complete(be, &reached[1])?,
+            cf: complete(cf, &reached[2])?,
+        })
+    }
+
+    /// Works out the products straight from a day's key, with the rotors at the Grundstellung.
+    /// Unlike the catalogue, this includes the plugboard and any turnover during the indicator.
+    pub fn from_key(key: EnigmaKey, reflector: impl Into<Reflector>) -> Self {
+        let mut enigma = Enigma::new(key, reflector);
+        let permutations: [Permutation; 6] = array::from_fn(|_| {
+            enigma.encrypt('A');
+            enigma.permutation()
+        });
+
+        Self::from_permutations(&permutations)
+    }
+
+    fn from_permutations(p: &[Permutation; 6]) -> Self {
+        Self {
+            ad: p[0].then(&p[3]),
+            be: p[1].then(&p[4]),
+            cf: p[2].then(&p[5]),
+        }
+    }
+
+    pub fn ad(&self) -> &Permutation {
+        &self.ad
+    }
+
+    pub fn be(&self) -> &Permutation {
+        &self.be
+    }
+
+    pub fn cf(&self) -> &Permutation {
+        &self.cf
+    }
+
+    /// Returns the cycle types of the products, which is what the catalogue is indexed by.
+    pub fn characteristic(&self) -> Characteristic {
+        Characteristic {
+            ad: self.ad.cycle_type(),
+            be: self.be.cycle_type(),
+            cf: self.cf.cycle_type(),
+        }
+    }
+}
+
+/// Fills in the product, which must be a partial permutation. If only one letter is missing, it can
+/// only go to the one letter nothing else reaches.
+fn complete(
+    mut product: [Option<u8>; 26],
+    reached: &[bool; 26],
+) -> Result<Permutation, EnigmaError> {
+    let mut missing = (0..26).filter(|&i| product[i as usize].is_none());
+    if let (Some(from), None) = (missing.next(), missing.next()) {
+        product[from as usize] = reached.iter().position(|r| !r).map(|to| to as u8);
+    }
+
+    let mut mapping = [0; 26];
+    for (i, (m, p)) in mapping.iter_mut().zip(&product).enumerate() {
+        *m = p.ok_or(EnigmaError::IncompleteCharacteristic(
+            (i as u8 + b'A') as char,
+        ))?;
+    }
+
+    Permutation::try_from(mapping)
+}
+
+/// The cycle types of AD, BE and CF. Their cycles always come in pairs of the same length, as each
+/// product is made of two involutions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Characteristic {
+    ad: CycleType,
+    be: CycleType,
+    cf: CycleType,
+}
+
+impl Characteristic {
+    pub fn ad(&self) -> &CycleType {
+        &self.ad
+    }
+
+    pub fn be(&self) -> &CycleType {
+        &self.be
+    }
+
+    pub fn cf(&self) -> &CycleType {
+        &self.cf
+    }
+}
+
+impl Display for Characteristic {
+    /// Prints the three cycle types separated by slashes, such as `13 13 / 12 12 1 1 / 10 10 3 3`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} / {} / {}", self.ad, self.be, self.cf)
+    }
+}
+
+/// A rotor order and the positions the rotors start the indicator at, with the ring settings at A.
+/// The real Grundstellung and ring settings are both further round by the same amount on each
+/// rotor, which the catalogue can't tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CatalogueEntry {
+    rotors: [RotorId; 3],
+    positions: [u8; 3],
+}
+
+impl CatalogueEntry {
+    /// Get a reference to the rotors, from left to right.
+    pub fn rotors(&self) -> &[RotorId; 3] {
+        &self.rotors
+    }
+
+    /// Get a reference to the rotor positions, from left to right.
+    pub fn positions(&self) -> &[u8; 3] {
+        &self.positions
+    }
+
+    /// Returns a key with the rotors at these positions, the ring settings at A and no plugs.
+    pub fn key(&self) -> EnigmaKey {
+        unplugged_key(self.rotors, self.positions, [0; 3])
+    }
+}
+
+/// Rejewski's card catalogue: the characteristic at every position of every rotor order.
+///
+/// Like the cyclometer it was made with, the catalogue assumes only the right rotor moves during the
+/// indicator. That's true at 20 of its 26 positions, and fewer if the middle rotor is at its own
+/// notch, so about a quarter of days won't be found.
+pub struct Catalogue {
+    reflector: ReflectorId,
+    entries: HashMap<Characteristic, Vec<CatalogueEntry>>,
+    len: usize,
+}
+
+impl Catalogue {
+    /// Builds the catalogue, which takes a few seconds for the five Wehrmacht rotors.
+    pub fn new(rotors: EnigmaAnalysisRotors, reflector: ReflectorId) -> Self {
+        let entries = rotors
+            .rotor_orders()
+            .into_par_iter()
+            .fold(HashMap::new, |mut entries, rotors| {
+                for (left, middle) in iproduct!(0..26, 0..26) {
+                    // Only the right rotor moves during the indicator, so each of its positions is
+                    // shared by six entries.
+                    let permutations = right_rotor_permutations(rotors, left, middle, reflector);
+
+                    for right in 0..26 {
+                        // The rotor steps before each key press, so the indicator starts one on.
+                        let indicator = array::from_fn(|i| permutations[(right + i + 1) % 26]);
+                        let entry = CatalogueEntry {
+                            rotors,
+                            positions: [left, middle, right as u8],
+                        };
+                        entries
+                            .entry(Products::from_permutations(&indicator).characteristic())
+                            .or_insert_with(Vec::new)
+                            .push(entry);
+                    }
+                }
+                entries
+            })
+            .reduce(HashMap::new, |mut entries, other| {
+                for (characteristic, mut other) in other {
+                    entries
+                        .entry(characteristic)
+                        .or_insert_with(Vec::new)
+                        .append(&mut other);
+                }
+                entries
+            });
+
+        let len = entries.values().map(Vec::len).sum();
+        Self {
+            reflector,
+            entries,
+            len,
+        }
+    }
+
+    /// Get the reflector the catalogue was made for.
+    pub fn reflector(&self) -> ReflectorId {
+        self.reflector
+    }
+
+    /// Get the number of entries, one for every position of every rotor order.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns every rotor order and position with this characteristic.
+    pub fn lookup(&self, characteristic: &Characteristic) -> &[CatalogueEntry] {
+        self.entries
+            .get(characteristic)
+            .map_or(&[], |entries| entries.as_slice())
+    }
+
+    /// Works out the characteristic of a day's doubled indicators, and returns the candidate rotor
+    /// orders and Grundstellungen.
+    pub fn find(&self, indicators: &[Indicator]) -> Result<&[CatalogueEntry], EnigmaError> {
+        let characteristic = Products::from_indicators(indicators)?.characteristic();
+        Ok(self.lookup(&characteristic))
+    }
+
+    /// Iterates over each characteristic in the catalogue, along with the entries that have it, in
+    /// no particular order.
+    pub fn characteristics(
+        &self,
+    ) -> impl Iterator<Item = (&Characteristic, &[CatalogueEntry])> + '_ {
+        self.entries
+            .iter()
+            .map(|(characteristic, entries)| (characteristic, entries.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enigma::stepping::Ratchet, procedure::encrypt_pre_1940};
+
+    // The right rotor starts at H, well before its notch at V, so only it moves during the indicator.
+    const DAY_KEY: &str = "II I III / K D H / F V M / AF TV KO BL RW QZ";
+    const GRUNDSTELLUNG: &str = "KDH";
+
+    fn indicators(key: &EnigmaKey, count: usize) -> Vec<Indicator> {
+        let mut seed = 1932_u64;
+        let mut letters = || -> String {
+            (0..3)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    ((seed >> 33) % 26) as u8 + b'A'
+                })
+                .map(char::from)
+                .collect()
+        };
+
+        (0..count)
+            .map(|_| {
+                let message_key = letters();
+                encrypt_pre_1940(
+                    key,
+                    ReflectorId::B,
+                    Ratchet,
+                    GRUNDSTELLUNG,
+                    &message_key,
+                    "",
+                )
+                .unwrap()
+                .indicator()
+                .clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn products_from_indicators() {
+        let key: EnigmaKey = DAY_KEY.parse().unwrap();
+        let products = Products::from_indicators(&indicators(&key, 150)).unwrap();
+        assert_eq!(products, Products::from_key(key, ReflectorId::B));
+
+        // Each product is made of two involutions, so its cycles come in pairs.
+        for cycle_type in &[products.ad().cycle_type(), products.be().cycle_type()] {
+            for pair in cycle_type.lengths().chunks(2) {
+                assert_eq!(pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_indicators() {
+        let indicator = |s: &str| Indicator::Doubled(s.to_owned());
+
+        assert_eq!(
+            Products::from_indicators(&[indicator("ABCDEF"), indicator("AXXEXX")]),
+            Err(EnigmaError::ConflictingIndicator("AXXEXX".to_owned()))
+        );
+        assert_eq!(
+            Products::from_indicators(&[indicator("ABCDE")]),
+            Err(EnigmaError::InvalidIndicator("ABCDE".to_owned()))
+        );
+        assert_eq!(
+            Products::from_indicators(&[indicator("ABCDEF")]),
+            Err(EnigmaError::IncompleteCharacteristic('B'))
+        );
+    }
+
+    #[test]
+    fn catalogue_finds_day_key() {
+        let key: EnigmaKey = DAY_KEY.parse().unwrap();
+        let catalogue = Catalogue::new(EnigmaAnalysisRotors::Three, ReflectorId::B);
+        assert_eq!(catalogue.len(), 6 * 26 * 26 * 26);
+
+        let entries = catalogue.find(&indicators(&key, 150)).unwrap();
+        assert!(entries.len() < catalogue.len() / 100);
+
+        // The catalogue has the ring settings at A, so the rotors are back by the ring settings.
+        let expected = CatalogueEntry {
+            rotors: [RotorId::II, RotorId::I, RotorId::III],
+            positions: [10 - 5, 3 + 26 - 21, 7 + 26 - 12],
+        };
+        assert!(entries.contains(&expected));
+    }
+}
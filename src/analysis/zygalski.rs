@@ -0,0 +1,409 @@
+// Zygalski's perforated sheets, used against the doubled indicators once operators picked their own
+// Grundstellung in September 1938. A female is an indicator with the same letter three places apart,
+// which can only happen where the product of the machine's permutations at those two places has a
+// fixed point. The plugboard doesn't change whether it has one, so a sheet for each rotor order and
+// left rotor position has a hole wherever the middle and right rotors allow a female. The ring
+// settings are unknown, but each female was sent at a Grundstellung in the clear, so for each guess
+// at the ring settings the sheets can be laid over each other with the Grundstellungen lined up.
+// Only ring settings where every female falls on a hole survive.
+
+use std::{array, fmt::Display};
+
+use itertools::iproduct;
+use rayon::prelude::*;
+
+use crate::{
+    enigma::{Enigma, EnigmaKey, ReflectorId, RotorId},
+    error::EnigmaError,
+    procedure::Indicator,
+};
+
+use super::{right_rotor_permutations, unplugged_key, EnigmaAnalysisRotors};
+
+/// An indicator with the same letter three places apart, along with the Grundstellung it was sent
+/// at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Female {
+    grundstellung: [u8; 3],
+    pair: u8,
+}
+
+impl Female {
+    /// Picks out the females from a day's indicators, which must all be doubled at a Grundstellung
+    /// sent in the clear. An indicator can have more than one female, or none.
+    pub fn find(indicators: &[Indicator]) -> Result<Vec<Self>, EnigmaError> {
+        let mut females = Vec::new();
+        for indicator in indicators {
+            let (grundstellung, doubled_key) = match indicator {
+                Indicator::DoubledAt {
+                    grundstellung,
+                    doubled_key,
+                } if grundstellung.len() == 3 && doubled_key.len() == 6 => {
+                    (grundstellung.as_bytes(), doubled_key.as_bytes())
+                }
+                _ => return Err(EnigmaError::InvalidIndicator(indicator.to_string())),
+            };
+            if let Some((position, &c)) = grundstellung
+                .iter()
+                .chain(doubled_key)
+                .enumerate()
+                .find(|(_, c)| !c.is_ascii_uppercase())
+            {
+                return Err(EnigmaError::InvalidCharacter {
+                    position,
+                    character: c as char,
+                });
+            }
+
+            let grundstellung = array::from_fn(|i| grundstellung[i] - b'A');
+            for pair in 0..3 {
+                if doubled_key[pair] == doubled_key[pair + 3] {
+                    females.push(Female {
+                        grundstellung,
+                        pair: pair as u8,
+                    });
+                }
+            }
+        }
+
+        Ok(females)
+    }
+
+    /// Get a reference to the Grundstellung the indicator was sent at, from left to right.
+    pub fn grundstellung(&self) -> &[u8; 3] {
+        &self.grundstellung
+    }
+
+    /// Get which letters matched: 0 for the first and fourth, 1 for the second and fifth, and 2 for
+    /// the third and sixth.
+    pub fn pair(&self) -> u8 {
+        self.pair
+    }
+
+    /// Whether only the right rotor moves up to the second letter of the female, so the sheets apply.
+    /// The notches turn with the alphabet ring, so this only depends on the Grundstellung.
+    fn is_usable(&self, rotors: [RotorId; 3], reflector: ReflectorId) -> bool {
+        let key = unplugged_key(rotors, self.grundstellung, [0; 3]);
+        let mut enigma = Enigma::new(key, reflector);
+        for _ in 0..self.pair + 4 {
+            enigma.encrypt('A');
+        }
+
+        let state = enigma.state();
+        let [left, middle, _] = self.grundstellung;
+        state.left_rotor().rotor_position() == left
+            && state.middle_rotor().rotor_position() == middle
+    }
+}
+
+/// One perforated sheet, for a rotor order and a left rotor position with the ring setting at A.
+/// It has a hole at each middle and right rotor position where an indicator starting there can have
+/// a female in its first and fourth letters. Females in the other letters use the same sheet, moved
+/// along by one or two places.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sheet {
+    rotors: [RotorId; 3],
+    left_position: u8,
+    // A bit for each right rotor position, indexed by the middle rotor position.
+    holes: [u32; 26],
+}
+
+impl Sheet {
+    /// Get a reference to the rotors, from left to right.
+    pub fn rotors(&self) -> &[RotorId; 3] {
+        &self.rotors
+    }
+
+    /// Get the left rotor's position.
+    pub fn left_position(&self) -> u8 {
+        self.left_position
+    }
+
+    /// Whether there's a hole where the middle and right rotors start at these positions.
+    pub fn has_hole(&self, middle_position: u8, right_position: u8) -> bool {
+        self.holes[middle_position as usize] & (1 << right_position) != 0
+    }
+
+    /// Get the number of holes, out of 676.
+    pub fn hole_count(&self) -> u32 {
+        self.holes.iter().map(|row| row.count_ones()).sum()
+    }
+}
+
+impl Display for Sheet {
+    /// Draws the sheet as a grid, with a row for each middle rotor position and a column for each
+    /// right rotor position. Holes are `O`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "  ")?;
+        for right in 0..26 {
+            write!(f, "{}", (right + b'A') as char)?;
+        }
+
+        for middle in 0..26 {
+            write!(f, "\n{} ", (middle + b'A') as char)?;
+            for right in 0..26 {
+                let c = if self.has_hole(middle, right) {
+                    'O'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A rotor order and ring settings that every usable female fell on a hole for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    rotors: [RotorId; 3],
+    ring_settings: [u8; 3],
+    females: usize,
+}
+
+impl Candidate {
+    /// Get a reference to the rotors, from left to right.
+    pub fn rotors(&self) -> &[RotorId; 3] {
+        &self.rotors
+    }
+
+    /// Get a reference to the ring settings, from left to right.
+    pub fn ring_settings(&self) -> &[u8; 3] {
+        &self.ring_settings
+    }
+
+    /// Get the number of females that were usable with this rotor order. The fewer there were, the
+    /// more likely the candidate is to be wrong.
+    pub fn females(&self) -> usize {
+        self.females
+    }
+
+    /// Returns a key with these rotors and ring settings, the rotors at A and no plugs. The rotors
+    /// should be turned to each message's Grundstellung.
+    pub fn key(&self) -> EnigmaKey {
+        unplugged_key(self.rotors, [0; 3], self.ring_settings)
+    }
+}
+
+/// A full set of sheets, 26 for each rotor order.
+pub struct Sheets {
+    reflector: ReflectorId,
+    sheets: Vec<Sheet>,
+}
+
+impl Sheets {
+    /// Makes the sheets, which takes about a second for the five Wehrmacht rotors.
+    pub fn new(rotors: EnigmaAnalysisRotors, reflector: ReflectorId) -> Self {
+        let sheets = rotors
+            .rotor_orders()
+            .into_par_iter()
+            .flat_map_iter(|rotors| {
+                (0..26).map(move |left| {
+                    let mut holes = [0; 26];
+                    for (middle, row) in (0..26).zip(&mut holes) {
+                        let permutations =
+                            right_rotor_permutations(rotors, left, middle, reflector);
+
+                        // The rotor steps before each key press, so the indicator starts one on.
+                        for right in 0..26 {
+                            let first = &permutations[(right + 1) % 26];
+                            let fourth = &permutations[(right + 4) % 26];
+                            if first.then(fourth).fixed_points().next().is_some() {
+                                *row |= 1 << right;
+                            }
+                        }
+                    }
+
+                    Sheet {
+                        rotors,
+                        left_position: left,
+                        holes,
+                    }
+                })
+            })
+            .collect();
+
+        Self { reflector, sheets }
+    }
+
+    /// Get the reflector the sheets were made for.
+    pub fn reflector(&self) -> ReflectorId {
+        self.reflector
+    }
+
+    /// Get a reference to the sheet for a rotor order and left rotor position.
+    pub fn get(&self, rotors: [RotorId; 3], left_position: u8) -> Option<&Sheet> {
+        self.sheets
+            .iter()
+            .find(|sheet| sheet.rotors == rotors && sheet.left_position == left_position)
+    }
+
+    /// Get the number of sheets.
+    pub fn len(&self) -> usize {
+        self.sheets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sheets.is_empty()
+    }
+
+    /// Stacks the sheets for every rotor order and ring setting, and returns those where all the
+    /// females fall on holes.
+    ///
+    /// Females where the middle rotor turns over during the indicator are left out for that rotor
+    /// order, as the sheets would be wrong for them. With no usable females at all, every ring
+    /// setting survives.
+    pub fn stack(&self, females: &[Female]) -> Vec<Candidate> {
+        self.sheets
+            .par_chunks(26)
+            .flat_map_iter(|sheets| {
+                let rotors = sheets[0].rotors;
+                let usable: Vec<_> = females
+                    .iter()
+                    .filter(|female| female.is_usable(rotors, self.reflector))
+                    .collect();
+
+                iproduct!(0..26, 0..26, 0..26).filter_map(move |(left, middle, right)| {
+                    let survives = usable.iter().all(|female| {
+                        let [g_left, g_middle, g_right] = female.grundstellung;
+                        let sheet = &sheets[((g_left + 26 - left) % 26) as usize];
+                        sheet.has_hole(
+                            (g_middle + 26 - middle) % 26,
+                            (g_right + 26 - right + female.pair) % 26,
+                        )
+                    });
+
+                    survives.then_some(Candidate {
+                        rotors,
+                        ring_settings: [left, middle, right],
+                        females: usable.len(),
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enigma::stepping::Ratchet, procedure::encrypt_1938};
+
+    const DAY_KEY: &str = "II I III / 1 1 1 / F V M / AF TV KO BL RW QZ";
+
+    fn indicators(key: &EnigmaKey, count: usize) -> Vec<Indicator> {
+        let mut seed = 1938_u64;
+        let mut letters = || -> String {
+            (0..3)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    ((seed >> 33) % 26) as u8 + b'A'
+                })
+                .map(char::from)
+                .collect()
+        };
+
+        (0..count)
+            .map(|_| {
+                let grundstellung = letters();
+                let message_key = letters();
+                encrypt_1938(
+                    key,
+                    ReflectorId::B,
+                    Ratchet,
+                    &grundstellung,
+                    &message_key,
+                    "",
+                )
+                .unwrap()
+                .indicator()
+                .clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_females() {
+        let indicator = |grundstellung: &str, doubled_key: &str| Indicator::DoubledAt {
+            grundstellung: grundstellung.to_owned(),
+            doubled_key: doubled_key.to_owned(),
+        };
+
+        let females = Female::find(&[indicator("ABC", "XYZXQZ"), indicator("KLM", "ABCDEF")]);
+        assert_eq!(
+            females,
+            Ok(vec![
+                Female {
+                    grundstellung: [0, 1, 2],
+                    pair: 0
+                },
+                Female {
+                    grundstellung: [0, 1, 2],
+                    pair: 2
+                }
+            ])
+        );
+
+        assert_eq!(
+            Female::find(&[indicator("ABc", "XYZXQZ")]),
+            Err(EnigmaError::InvalidCharacter {
+                position: 2,
+                character: 'c'
+            })
+        );
+        assert_eq!(
+            Female::find(&[Indicator::Doubled("XYZXQZ".to_owned())]),
+            Err(EnigmaError::InvalidIndicator("XYZXQZ".to_owned()))
+        );
+
+        let key: EnigmaKey = DAY_KEY.parse().unwrap();
+        assert!(!Female::find(&indicators(&key, 300)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn females_fall_on_holes() {
+        let key: EnigmaKey = DAY_KEY.parse().unwrap();
+        let rotors = [RotorId::II, RotorId::I, RotorId::III];
+        let rings = [5, 21, 12];
+        let sheets = Sheets::new(EnigmaAnalysisRotors::Three, ReflectorId::B);
+        assert_eq!(sheets.len(), 6 * 26);
+
+        let females = Female::find(&indicators(&key, 300)).unwrap();
+        let mut usable = 0;
+        for female in females
+            .iter()
+            .filter(|f| f.is_usable(rotors, ReflectorId::B))
+        {
+            // The sheets have the ring settings at A, so the rotors are back by the ring settings.
+            let [left, middle, right] = female.grundstellung;
+            let sheet = sheets.get(rotors, (left + 26 - rings[0]) % 26).unwrap();
+            assert!(sheet.has_hole(
+                (middle + 26 - rings[1]) % 26,
+                (right + 26 - rings[2] + female.pair) % 26
+            ));
+            usable += 1;
+        }
+        assert!(usable > 10, "only {} usable females", usable);
+    }
+
+    #[test]
+    fn stacking_keeps_day_key() {
+        let key: EnigmaKey = DAY_KEY.parse().unwrap();
+        let sheets = Sheets::new(EnigmaAnalysisRotors::Three, ReflectorId::B);
+        let females = Female::find(&indicators(&key, 300)).unwrap();
+
+        let candidates = sheets.stack(&females);
+        assert!(candidates.len() < 10, "{} candidates", candidates.len());
+        assert!(candidates.iter().any(|candidate| {
+            candidate.rotors() == &[RotorId::II, RotorId::I, RotorId::III]
+                && candidate.ring_settings() == &[5, 21, 12]
+        }));
+
+        // With no females, nothing is ruled out.
+        assert_eq!(sheets.stack(&[]).len(), 6 * 26 * 26 * 26);
+    }
+}
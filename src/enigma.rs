@@ -1,79 +1,353 @@
-use std::fmt::{Display, Write};
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum RotorId {
-    I = 0,
-    II = 1,
-    III = 2,
-    IV = 3,
-    V = 4,
-    VI = 5,
-    VII = 6,
-    VIII = 7,
-
-    Identity = 8,
-}
-
-// Because the rotor wiring is a fixed value, I decided to generate these at compile time. The Java version
-// generates them at runtime, but uses fixed values, so the end result never changes.
-// But, if you did want to have these be a run-time input, the way you'd do it would be to generate them
-// once at program startup, then you could have RotorID be a struct that holds the notch positions and a
-// reference to the mapping arrays and returns them in the forward_wiring and reverse_wiring functions.
-const ROTOR_FORWARD_WIRING: [[u8; 26]; 9] = [
-    RotorId::gen_forward_wiring(RotorId::I),
-    RotorId::gen_forward_wiring(RotorId::II),
-    RotorId::gen_forward_wiring(RotorId::III),
-    RotorId::gen_forward_wiring(RotorId::IV),
-    RotorId::gen_forward_wiring(RotorId::V),
-    RotorId::gen_forward_wiring(RotorId::VI),
-    RotorId::gen_forward_wiring(RotorId::VII),
-    RotorId::gen_forward_wiring(RotorId::VIII),
-    RotorId::gen_forward_wiring(RotorId::Identity),
-];
+pub mod batch;
+pub mod machine;
+pub mod permutation;
+pub mod scrambler;
+pub mod stepping;
+pub mod stream;
+mod svg;
+pub mod trace;
+pub mod uhr;
+pub mod ukwd;
+
+use std::{
+    borrow::Cow,
+    convert::TryFrom,
+    fmt::{Debug, Display},
+    ops::Deref,
+    str::FromStr,
+};
+
+use crate::error::EnigmaError;
+use machine::Machine;
+use permutation::Permutation;
+use scrambler::ScramblerTables;
+use stepping::{Cog, Ratchet, Stepping};
+use trace::Trace;
+use uhr::Uhr;
+use ukwd::UkwD;
+
+/// Decodes a 26-letter wiring string into a permutation of 0..26.
+fn decode_wiring(wiring: &str) -> Result<[u8; 26], EnigmaError> {
+    let len = wiring.chars().count();
+    if len != 26 {
+        return Err(EnigmaError::InvalidLength(len));
+    }
+
+    let mut mapping = [0; 26];
+    let mut seen = [false; 26];
+
+    for (position, (c, m)) in wiring.chars().zip(&mut mapping).enumerate() {
+        if !c.is_ascii_uppercase() {
+            return Err(EnigmaError::InvalidCharacter {
+                position,
+                character: c,
+            });
+        }
+
+        let v = c as u8 - b'A';
+        if seen[v as usize] {
+            return Err(EnigmaError::DuplicateLetter {
+                position,
+                letter: c,
+            });
+        }
+
+        seen[v as usize] = true;
+        *m = v;
+    }
+
+    Ok(mapping)
+}
+
+/// Converts uppercase ASCII text to the machine's internal alphabet, where A is 0 and Z is 25.
+pub fn to_letters(text: &str) -> Result<Vec<u8>, EnigmaError> {
+    text.chars()
+        .enumerate()
+        .map(|(position, c)| {
+            if c.is_ascii_uppercase() {
+                Ok(c as u8 - b'A')
+            } else {
+                Err(EnigmaError::InvalidCharacter {
+                    position,
+                    character: c,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Converts letters in the machine's internal alphabet back to uppercase ASCII text. Requires that
+/// the letters are in the range 0..26.
+pub fn from_letters(letters: &[u8]) -> String {
+    letters.iter().map(|&c| (c + b'A') as char).collect()
+}
+
+fn check_position(position: u8) -> Result<(), EnigmaError> {
+    if position < 26 {
+        Ok(())
+    } else {
+        Err(EnigmaError::InvalidPosition(position))
+    }
+}
+
+fn check_ring_setting(ring_setting: u8) -> Result<(), EnigmaError> {
+    if ring_setting < 26 {
+        Ok(())
+    } else {
+        Err(EnigmaError::InvalidRingSetting(ring_setting))
+    }
+}
+
+/// Parses a position or ring setting as written on a key sheet, either as a number from 1 to 26 or as
+/// a letter in either case, into the range 0..26.
+fn parse_setting(setting: &str) -> Result<u8, EnigmaError> {
+    let invalid = || EnigmaError::InvalidSetting(setting.to_owned());
+
+    let mut chars = setting.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Ok(c.to_ascii_uppercase() as u8 - b'A'),
+        _ => match setting.parse::<u8>() {
+            Ok(v @ 1..=26) => Ok(v - 1),
+            _ => Err(invalid()),
+        },
+    }
+}
 
-const ROTOR_BACKWARD_WIRING: [[u8; 26]; 9] = [
-    RotorId::gen_backward_wiring(RotorId::I),
-    RotorId::gen_backward_wiring(RotorId::II),
-    RotorId::gen_backward_wiring(RotorId::III),
-    RotorId::gen_backward_wiring(RotorId::IV),
-    RotorId::gen_backward_wiring(RotorId::V),
-    RotorId::gen_backward_wiring(RotorId::VI),
-    RotorId::gen_backward_wiring(RotorId::VII),
-    RotorId::gen_backward_wiring(RotorId::VIII),
-    RotorId::gen_backward_wiring(RotorId::Identity),
+// Because the standard rotor wiring is a fixed value, I decided to generate these at compile time. The Java
+// version generates them at runtime, but uses fixed values, so the end result never changes.
+// Custom rotors are generated once at runtime, then leaked so that a RotorId can still just be a reference
+// to the wiring and remain Copy. You'll want to create them once up front rather than in a loop.
+#[derive(Debug, PartialEq, Eq)]
+struct RotorWiring {
+    name: Cow<'static, str>,
+    forward: [u8; 26],
+    backward: [u8; 26],
+    // Bit N is set if the rotor is at a notch at position N.
+    notches: u32,
+}
+
+impl RotorWiring {
+    const fn standard(name: &'static str, chars: &[u8; 26], notches: &[u8]) -> Self {
+        let mut forward = [0; 26];
+        let mut backward = [0; 26];
+
+        let mut i = 0;
+        while i < 26 {
+            forward[i] = chars[i] - b'A';
+            backward[forward[i] as usize] = i as u8;
+            i += 1;
+        }
+
+        let mut notch_bits = 0;
+        let mut i = 0;
+        while i < notches.len() {
+            notch_bits |= 1 << (notches[i] - b'A');
+            i += 1;
+        }
+
+        Self {
+            name: Cow::Borrowed(name),
+            forward,
+            backward,
+            notches: notch_bits,
+        }
+    }
+}
+
+const ROTOR_WIRING: [RotorWiring; 23] = [
+    RotorWiring::standard("I", b"EKMFLGDQVZNTOWYHXUSPAIBRCJ", b"Q"),
+    RotorWiring::standard("II", b"AJDKSIRUXBLHWTMCQGZNPYFVOE", b"E"),
+    RotorWiring::standard("III", b"BDFHJLCPRTXVZNYEIWGAKMUSQO", b"V"),
+    RotorWiring::standard("IV", b"ESOVPZJAYQUIRHXLNFTGKDCMWB", b"J"),
+    RotorWiring::standard("V", b"VZBRGITYUPSDNHLXAWMJQOFECK", b"Z"),
+    RotorWiring::standard("VI", b"JPGVOUMFYQBENHZRDKASXLICTW", b"MZ"),
+    RotorWiring::standard("VII", b"NZJHGRCXMYSWBOUFAIVLPEKQDT", b"MZ"),
+    RotorWiring::standard("VIII", b"FKQHTLXOCBJSPDZRAMEWNIUYGV", b"MZ"),
+    RotorWiring::standard("Identity", b"ABCDEFGHIJKLMNOPQRSTUVWXYZ", b"A"),
+    // The Kriegsmarine M4's thin "Greek" wheels. These sit between the left rotor and the reflector
+    // and never step.
+    RotorWiring::standard("Beta", b"LEYJVCNIXWPBQMDRTAKZGFUHOS", b""),
+    RotorWiring::standard("Gamma", b"FSOKANUERHMBTIYCWLQPZXVGJD", b""),
+    // The commercial Enigma D, and the K sold to the Swiss.
+    RotorWiring::standard("D-I", b"LPGSZMHAEOQKVXRFYBUTNICJDW", b"Y"),
+    RotorWiring::standard("D-II", b"SLVGBTFXJQOHEWIRZYAMKPCNDU", b"E"),
+    RotorWiring::standard("D-III", b"CJGDPSHKTURAWZXFMYNQOBVLIE", b"N"),
+    RotorWiring::standard("K-I", b"PEZUOHXSCVFMTBGLRINQJWAYDK", b"Y"),
+    RotorWiring::standard("K-II", b"ZOUESYDKFWPCIQXHMVBLGNJRAT", b"E"),
+    RotorWiring::standard("K-III", b"EHRVXGAOBQUSIMZFLYNWKTPDJC", b"N"),
+    // The Abwehr's Enigma G, whose rotors have many notches.
+    RotorWiring::standard(
+        "G312-I",
+        b"DMTWSILRUYQNKFEJCAZBPGXOHV",
+        b"SUVWZABCEFGIKLOPQ",
+    ),
+    RotorWiring::standard("G312-II", b"HQZGPJTMOBLNCIFDYAWVEUSRKX", b"STVYZACDFGHKMNQ"),
+    RotorWiring::standard("G312-III", b"UQNTLSZFMREHDPXKIBVYGJCWOA", b"UWXAEFHKMNR"),
+    RotorWiring::standard(
+        "G260-I",
+        b"RCSPBLKQAUMHWYTIFZVGOJNEXD",
+        b"SUVWZABCEFGIKLOPQ",
+    ),
+    RotorWiring::standard("G260-II", b"WCMIBVPJXAROSGNDLZKEYHUFQT", b"STVYZACDFGHKMNQ"),
+    RotorWiring::standard("G260-III", b"FVDHZELSQMAXOKYIWPGCBUJTNR", b"UWXAEFHKMNR"),
 ];
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct RotorId(&'static RotorWiring);
+
+// These used to be enum variants, so keep their names.
+#[allow(non_upper_case_globals)]
+impl RotorId {
+    pub const I: Self = Self(&ROTOR_WIRING[0]);
+    pub const II: Self = Self(&ROTOR_WIRING[1]);
+    pub const III: Self = Self(&ROTOR_WIRING[2]);
+    pub const IV: Self = Self(&ROTOR_WIRING[3]);
+    pub const V: Self = Self(&ROTOR_WIRING[4]);
+    pub const VI: Self = Self(&ROTOR_WIRING[5]);
+    pub const VII: Self = Self(&ROTOR_WIRING[6]);
+    pub const VIII: Self = Self(&ROTOR_WIRING[7]);
+
+    pub const Identity: Self = Self(&ROTOR_WIRING[8]);
+
+    pub const Beta: Self = Self(&ROTOR_WIRING[9]);
+    pub const Gamma: Self = Self(&ROTOR_WIRING[10]);
+
+    pub const D_I: Self = Self(&ROTOR_WIRING[11]);
+    pub const D_II: Self = Self(&ROTOR_WIRING[12]);
+    pub const D_III: Self = Self(&ROTOR_WIRING[13]);
+
+    pub const K_I: Self = Self(&ROTOR_WIRING[14]);
+    pub const K_II: Self = Self(&ROTOR_WIRING[15]);
+    pub const K_III: Self = Self(&ROTOR_WIRING[16]);
+
+    pub const G312_I: Self = Self(&ROTOR_WIRING[17]);
+    pub const G312_II: Self = Self(&ROTOR_WIRING[18]);
+    pub const G312_III: Self = Self(&ROTOR_WIRING[19]);
+
+    pub const G260_I: Self = Self(&ROTOR_WIRING[20]);
+    pub const G260_II: Self = Self(&ROTOR_WIRING[21]);
+    pub const G260_III: Self = Self(&ROTOR_WIRING[22]);
+
+    const BUILT_IN: [Self; 23] = [
+        Self::I,
+        Self::II,
+        Self::III,
+        Self::IV,
+        Self::V,
+        Self::VI,
+        Self::VII,
+        Self::VIII,
+        Self::Identity,
+        Self::Beta,
+        Self::Gamma,
+        Self::D_I,
+        Self::D_II,
+        Self::D_III,
+        Self::K_I,
+        Self::K_II,
+        Self::K_III,
+        Self::G312_I,
+        Self::G312_II,
+        Self::G312_III,
+        Self::G260_I,
+        Self::G260_II,
+        Self::G260_III,
+    ];
+}
+
 impl RotorId {
+    /// Creates a rotor from a 26-letter wiring string, where the Nth letter is the contact the Nth
+    /// letter of the alphabet is wired to. The notches are the rotor positions, in the range 0..26,
+    /// at which the next rotor is stepped. The name can't be one of the built-in rotors', ignoring
+    /// case.
+    ///
+    /// The wiring is leaked so that `RotorId` can stay `Copy`, and there's no way to free it. Each
+    /// call leaks a little more memory, so create custom rotors once up front rather than in a loop.
+    pub fn custom(name: &str, wiring: &str, notches: &[u8]) -> Result<Self, EnigmaError> {
+        if name.parse::<Self>().is_ok() {
+            return Err(EnigmaError::ReservedName(name.to_owned()));
+        }
+
+        let forward = decode_wiring(wiring)?;
+
+        let mut backward = [0; 26];
+        for (i, &f) in forward.iter().enumerate() {
+            backward[f as usize] = i as u8;
+        }
+
+        let mut notch_bits = 0;
+        for (position, &notch) in notches.iter().enumerate() {
+            if notch >= 26 {
+                return Err(EnigmaError::InvalidNotch { position, notch });
+            }
+            notch_bits |= 1 << notch;
+        }
+
+        let wiring = RotorWiring {
+            name: Cow::Owned(name.to_owned()),
+            forward,
+            backward,
+            notches: notch_bits,
+        };
+
+        Ok(Self(Box::leak(Box::new(wiring))))
+    }
+
+    /// Get the rotor's name.
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
     fn is_at_notch(self, position: u8) -> bool {
-        match self {
-            RotorId::I => position == 16,
-            RotorId::II => position == 4,
-            RotorId::III => position == 21,
-            RotorId::IV => position == 9,
-            RotorId::V => position == 25,
-            RotorId::VI => position == 12 || position == 25,
-            RotorId::VII => position == 12 || position == 25,
-            RotorId::VIII => position == 12 || position == 25,
-            RotorId::Identity => position == 0,
-        }
-    }
-
-    const fn chars(s: Self) -> [u8; 26] {
-        *match s {
-            RotorId::I => b"EKMFLGDQVZNTOWYHXUSPAIBRCJ",
-            RotorId::II => b"AJDKSIRUXBLHWTMCQGZNPYFVOE",
-            RotorId::III => b"BDFHJLCPRTXVZNYEIWGAKMUSQO",
-            RotorId::IV => b"ESOVPZJAYQUIRHXLNFTGKDCMWB",
-            RotorId::V => b"VZBRGITYUPSDNHLXAWMJQOFECK",
-            RotorId::VI => b"JPGVOUMFYQBENHZRDKASXLICTW",
-            RotorId::VII => b"NZJHGRCXMYSWBOUFAIVLPEKQDT",
-            RotorId::VIII => b"FKQHTLXOCBJSPDZRAMEWNIUYGV",
-            RotorId::Identity => b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
-        }
-    }
-
-    const fn gen_forward_wiring(s: Self) -> [u8; 26] {
-        let chars = Self::chars(s);
+        self.0.notches & (1 << position) != 0
+    }
+
+    fn notch_count(self) -> u32 {
+        self.0.notches.count_ones()
+    }
+
+    fn forward_wiring(self) -> &'static [u8; 26] {
+        &self.0.forward
+    }
+
+    fn backward_wiring(self) -> &'static [u8; 26] {
+        &self.0.backward
+    }
+}
+
+impl Debug for RotorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Display for RotorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RotorId {
+    type Err = EnigmaError;
+
+    /// Looks up a built-in rotor by name, ignoring case. Custom rotors can't be parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::BUILT_IN
+            .iter()
+            .find(|id| id.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| EnigmaError::UnknownRotor(s.to_owned()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ReflectorWiring {
+    name: Cow<'static, str>,
+    wiring: [u8; 26],
+}
+
+impl ReflectorWiring {
+    const fn standard(name: &'static str, chars: &[u8; 26]) -> Self {
         let mut wiring = [0; 26];
 
         let mut i = 0;
@@ -82,70 +356,381 @@ impl RotorId {
             i += 1;
         }
 
-        wiring
+        Self {
+            name: Cow::Borrowed(name),
+            wiring,
+        }
     }
+}
 
-    const fn gen_backward_wiring(s: Self) -> [u8; 26] {
-        let forward_wiring = Self::gen_forward_wiring(s);
-        let mut backwards_wiring = [0; 26];
+const REFLECTOR_WIRING: [ReflectorWiring; 9] = [
+    ReflectorWiring::standard("B", b"YRUHQSLDPXNGOKMIEBFZCWVJAT"),
+    ReflectorWiring::standard("C", b"FVPJIAOYEDRZXWGCTKUQSBNMHL"),
+    ReflectorWiring::standard("Default", b"ZYXWVUTSRQPONMLKJIHGFEDCBA"),
+    // The thin reflectors used with the M4's Greek wheels.
+    ReflectorWiring::standard("B-thin", b"ENKQAUYWJICOPBLMDXZVFTHRGS"),
+    ReflectorWiring::standard("C-thin", b"RDOBJNTKVEHMLFCWZAXGYIPSUQ"),
+    // The commercial machines' reflectors, which could be set to a position.
+    ReflectorWiring::standard("D-UKW", b"IMETCGFRAYSQBZXWLHKDVUPOJN"),
+    ReflectorWiring::standard("K-UKW", b"IMETCGFRAYSQBZXWLHKDVUPOJN"),
+    // The Enigma G's reflectors are also stepped by the rotors.
+    ReflectorWiring::standard("G312-UKW", b"RULQMZJSYGOCETKWDAHNBXPVIF"),
+    ReflectorWiring::standard("G260-UKW", b"IMETCGFRAYSQBZXWLHKDVUPOJN"),
+];
 
-        let mut i = 0;
-        while i < 26 {
-            backwards_wiring[forward_wiring[i] as usize] = i as u8;
-            i += 1;
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ReflectorId(&'static ReflectorWiring);
+
+// These used to be enum variants, so keep their names.
+#[allow(non_upper_case_globals)]
+impl ReflectorId {
+    pub const B: Self = Self(&REFLECTOR_WIRING[0]);
+    pub const C: Self = Self(&REFLECTOR_WIRING[1]);
+    pub const Default: Self = Self(&REFLECTOR_WIRING[2]);
+
+    pub const BThin: Self = Self(&REFLECTOR_WIRING[3]);
+    pub const CThin: Self = Self(&REFLECTOR_WIRING[4]);
+
+    pub const D_UKW: Self = Self(&REFLECTOR_WIRING[5]);
+    pub const K_UKW: Self = Self(&REFLECTOR_WIRING[6]);
+
+    pub const G312_UKW: Self = Self(&REFLECTOR_WIRING[7]);
+    pub const G260_UKW: Self = Self(&REFLECTOR_WIRING[8]);
+
+    const BUILT_IN: [Self; 9] = [
+        Self::B,
+        Self::C,
+        Self::Default,
+        Self::BThin,
+        Self::CThin,
+        Self::D_UKW,
+        Self::K_UKW,
+        Self::G312_UKW,
+        Self::G260_UKW,
+    ];
+}
+
+impl ReflectorId {
+    /// Creates a reflector from a 26-letter pairing string, where the Nth letter is the partner of
+    /// the Nth letter of the alphabet. Every letter must be paired with a different letter, which is
+    /// paired back with it.
+    /// The name can't be one of the built-in reflectors', ignoring case.
+    ///
+    /// Like custom rotors, the wiring is leaked so that `ReflectorId` can stay `Copy`, and there's no
+    /// way to free it. Create custom reflectors once up front rather than in a loop.
+    pub fn custom(name: &str, pairing: &str) -> Result<Self, EnigmaError> {
+        if name.parse::<Self>().is_ok() {
+            return Err(EnigmaError::ReservedName(name.to_owned()));
+        }
+
+        let wiring = decode_wiring(pairing)?;
+
+        for (i, &w) in wiring.iter().enumerate() {
+            if w as usize == i || wiring[w as usize] as usize != i {
+                return Err(EnigmaError::NotInvolution {
+                    letter: (i as u8 + b'A') as char,
+                    mapped: (w + b'A') as char,
+                });
+            }
         }
 
-        backwards_wiring
+        let wiring = ReflectorWiring {
+            name: Cow::Owned(name.to_owned()),
+            wiring,
+        };
+
+        Ok(Self(Box::leak(Box::new(wiring))))
     }
 
-    fn forward_wiring(self) -> &'static [u8; 26] {
-        &ROTOR_FORWARD_WIRING[self as usize]
+    /// Get the reflector's name.
+    pub fn name(&self) -> &str {
+        &self.0.name
     }
 
-    fn backward_wiring(self) -> &'static [u8; 26] {
-        &ROTOR_BACKWARD_WIRING[self as usize]
+    fn wiring(self) -> &'static [u8; 26] {
+        &self.0.wiring
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum ReflectorId {
-    B = 0,
-    C = 1,
-    Default = 2,
+impl Debug for ReflectorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-const REFLECTOR_WIRING: [[u8; 26]; 3] = [
-    ReflectorId::gen_wiring(ReflectorId::B),
-    ReflectorId::gen_wiring(ReflectorId::C),
-    ReflectorId::gen_wiring(ReflectorId::Default),
-];
+impl Display for ReflectorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
-impl ReflectorId {
-    const fn gen_wiring(self) -> [u8; 26] {
-        let mut wiring = *match self {
-            ReflectorId::B => b"YRUHQSLDPXNGOKMIEBFZCWVJAT",
-            ReflectorId::C => b"FVPJIAOYEDRZXWGCTKUQSBNMHL",
-            ReflectorId::Default => b"ZYXWVUTSRQPONMLKJIHGFEDCBA",
+impl FromStr for ReflectorId {
+    type Err = EnigmaError;
+
+    /// Looks up a built-in reflector by name, ignoring case. Custom reflectors can't be parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::BUILT_IN
+            .iter()
+            .find(|id| id.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| EnigmaError::UnknownReflector(s.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReflectorKind {
+    Fixed(ReflectorId),
+    UkwD(UkwD),
+}
+
+impl ReflectorKind {
+    fn wiring(&self) -> &[u8; 26] {
+        match self {
+            ReflectorKind::Fixed(id) => id.wiring(),
+            ReflectorKind::UkwD(ukwd) => ukwd.wiring(),
+        }
+    }
+}
+
+impl From<ReflectorId> for ReflectorKind {
+    fn from(id: ReflectorId) -> Self {
+        ReflectorKind::Fixed(id)
+    }
+}
+
+impl From<UkwD> for ReflectorKind {
+    fn from(ukwd: UkwD) -> Self {
+        ReflectorKind::UkwD(ukwd)
+    }
+}
+
+/// The reflector fitted to a machine, either one of the fixed reflectors or a field-wired UKW-D.
+/// The military reflectors sit at position 0, but the commercial machines' reflector can be set to a
+/// position and has its own ring setting.
+/// The M4's Greek wheel never moves and sits next to the reflector, so it's folded in with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reflector {
+    kind: ReflectorKind,
+    position: u8,
+    ring_setting: u8,
+    greek_rotor: Option<Rotor>,
+    // The wiring as seen at the current position and ring setting, and through the Greek wheel,
+    // so the hot path only needs a lookup.
+    wiring: [u8; 26],
+}
+
+impl Reflector {
+    pub fn new(kind: impl Into<ReflectorKind>, position: u8, ring_setting: u8) -> Self {
+        Self::try_new(kind, position, ring_setting).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(
+        kind: impl Into<ReflectorKind>,
+        position: u8,
+        ring_setting: u8,
+    ) -> Result<Self, EnigmaError> {
+        check_position(position)?;
+        check_ring_setting(ring_setting)?;
+
+        let mut reflector = Self {
+            kind: kind.into(),
+            position,
+            ring_setting,
+            greek_rotor: None,
+            wiring: [0; 26],
+        };
+        reflector.update_wiring();
+
+        Ok(reflector)
+    }
+
+    fn update_wiring(&mut self) {
+        let mut wiring = [0; 26];
+        for (c, w) in (0..).zip(&mut wiring) {
+            *w = self.reflect(c);
+        }
+        self.wiring = wiring;
+
+        if let Some(greek_rotor) = &self.greek_rotor {
+            let reflector = self.wiring;
+            for (c, w) in (0..).zip(&mut self.wiring) {
+                *w = greek_rotor.backward(reflector[greek_rotor.forward(c) as usize]);
+            }
+        }
+    }
+
+    fn forward(&self, c: u8) -> u8 {
+        self.wiring[c as usize]
+    }
+
+    /// Passes `c` through the reflector alone, leaving out the Greek wheel. This is slower than
+    /// `forward`, as it doesn't use the cached wiring.
+    fn reflect(&self, c: u8) -> u8 {
+        Rotor::encypher(c, self.position, self.ring_setting, self.kind.wiring())
+    }
+
+    /// The contact of the reflector's own wiring that `c` arrives at, after the Greek wheel and with
+    /// the reflector turned to its position. This is where the reflector could be rewired without
+    /// touching anything else.
+    fn contact(&self, c: u8) -> u8 {
+        let c = match &self.greek_rotor {
+            Some(greek_rotor) => greek_rotor.forward(c),
+            None => c,
+        };
+
+        (c + self.offset()) % 26
+    }
+
+    /// How far the wiring is turned relative to the contacts.
+    fn offset(&self) -> u8 {
+        (self.position + 26 - self.ring_setting) % 26
+    }
+
+    /// Get a reference to the M4 Greek wheel fitted next to the reflector.
+    pub fn greek_rotor(&self) -> Option<&Rotor> {
+        self.greek_rotor.as_ref()
+    }
+
+    /// Set the M4 Greek wheel fitted next to the reflector.
+    pub fn set_greek_rotor(&mut self, greek_rotor: Option<Rotor>) {
+        self.greek_rotor = greek_rotor;
+        self.update_wiring();
+    }
+
+    /// Get a reference to the reflector's kind.
+    pub fn kind(&self) -> &ReflectorKind {
+        &self.kind
+    }
+
+    /// Get the reflector's position.
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    /// Get the reflector's ring setting.
+    pub fn ring_setting(&self) -> u8 {
+        self.ring_setting
+    }
+
+    /// Set the reflector's position.
+    pub fn set_position(&mut self, position: u8) {
+        self.try_set_position(position)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Set the reflector's position, if it's in the range 0..26.
+    pub fn try_set_position(&mut self, position: u8) -> Result<(), EnigmaError> {
+        check_position(position)?;
+        self.position = position;
+        self.update_wiring();
+        Ok(())
+    }
+
+    /// Set the reflector's ring setting.
+    pub fn set_ring_setting(&mut self, ring_setting: u8) {
+        self.try_set_ring_setting(ring_setting)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Set the reflector's ring setting, if it's in the range 0..26.
+    pub fn try_set_ring_setting(&mut self, ring_setting: u8) -> Result<(), EnigmaError> {
+        check_ring_setting(ring_setting)?;
+        self.ring_setting = ring_setting;
+        self.update_wiring();
+        Ok(())
+    }
+
+    /// Steps the reflector on to its next position.
+    pub fn turnover(&mut self) {
+        self.position = match self.position + 1 {
+            v @ 0..=25 => v,
+            v => v - 26,
         };
+        self.update_wiring();
+    }
+
+    /// Steps the reflector on `steps` times at once.
+    fn advance(&mut self, steps: u64) {
+        let partial = (steps % 26) as u8;
+        if partial != 0 {
+            self.position = (self.position + partial) % 26;
+            self.update_wiring();
+        }
+    }
+}
+
+impl From<ReflectorId> for Reflector {
+    fn from(id: ReflectorId) -> Self {
+        Reflector::new(id, 0, 0)
+    }
+}
+
+impl From<UkwD> for Reflector {
+    fn from(ukwd: UkwD) -> Self {
+        Reflector::new(ukwd, 0, 0)
+    }
+}
+
+/// The entry wheel (Eintrittswalze) connects the keyboard to the rotors. The military machines wire it
+/// straight through, but the commercial machines wired it in keyboard order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryWheel {
+    forward: [u8; 26],
+    backward: [u8; 26],
+}
+
+impl EntryWheel {
+    pub const IDENTITY: Self = Self::standard(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+    pub const QWERTZU: Self = Self::standard(b"QWERTZUIOASDFGHJKPYXCVBNML");
+
+    const fn standard(chars: &[u8; 26]) -> Self {
+        let mut forward = [0; 26];
+        let mut backward = [0; 26];
 
         let mut i = 0;
         while i < 26 {
-            wiring[i] -= b'A';
+            backward[i] = chars[i] - b'A';
+            forward[backward[i] as usize] = i as u8;
             i += 1;
         }
-        wiring
+
+        Self { forward, backward }
     }
 
-    fn forward(self, c: u8) -> u8 {
-        REFLECTOR_WIRING[self as usize][c as usize]
+    /// Creates an entry wheel from a 26-letter string, where the Nth letter is the key wired to the
+    /// Nth contact. So the commercial wheel is "QWERTZUIOASDFGHJKPYXCVBNML".
+    pub fn custom(wiring: &str) -> Result<Self, EnigmaError> {
+        let backward = decode_wiring(wiring)?;
+
+        let mut forward = [0; 26];
+        for (i, &b) in backward.iter().enumerate() {
+            forward[b as usize] = i as u8;
+        }
+
+        Ok(Self { forward, backward })
+    }
+
+    fn forward(&self, c: u8) -> u8 {
+        self.forward[c as usize]
+    }
+
+    fn backward(&self, c: u8) -> u8 {
+        self.backward[c as usize]
+    }
+}
+
+impl Default for EntryWheel {
+    fn default() -> Self {
+        Self::IDENTITY
     }
 }
 
 // Because the Rotors are just a couple numbers, this ends up being massively cheaper to create
 // than in the Java version, which re-parses the rotor wiring each time.
-// The Plugboard is still parsed at runtime, but the type is only 26 bytes, so is cheap to copy.
+// The Plugboard is still parsed at runtime, but the type is only a few dozen bytes, so is cheap to copy.
 #[derive(Debug, Clone, Copy)]
 pub struct EnigmaKey {
+    greek_rotor: Option<Rotor>,
     left_rotor: Rotor,
     middle_rotor: Rotor,
     right_rotor: Rotor,
@@ -160,6 +745,7 @@ impl EnigmaKey {
         plugboard: Plugboard,
     ) -> Self {
         Self {
+            greek_rotor: None,
             left_rotor,
             middle_rotor,
             right_rotor,
@@ -167,6 +753,29 @@ impl EnigmaKey {
         }
     }
 
+    /// Creates a key for the four-rotor Kriegsmarine M4. The Greek wheel sits to the left of the
+    /// left rotor, and should be paired with one of the thin reflectors.
+    pub fn new_m4(
+        greek_rotor: Rotor,
+        left_rotor: Rotor,
+        middle_rotor: Rotor,
+        right_rotor: Rotor,
+        plugboard: Plugboard,
+    ) -> Self {
+        Self {
+            greek_rotor: Some(greek_rotor),
+            left_rotor,
+            middle_rotor,
+            right_rotor,
+            plugboard,
+        }
+    }
+
+    /// Get a reference to the enigma key's Greek rotor, if this is an M4 key.
+    pub fn greek_rotor(&self) -> Option<&Rotor> {
+        self.greek_rotor.as_ref()
+    }
+
     /// Get a reference to the enigma key's left rotor.
     pub fn left_rotor(&self) -> &Rotor {
         &self.left_rotor
@@ -182,6 +791,11 @@ impl EnigmaKey {
         &self.right_rotor
     }
 
+    /// Get a mutable reference to the enigma key's Greek rotor, if this is an M4 key.
+    pub fn greek_rotor_mut(&mut self) -> Option<&mut Rotor> {
+        self.greek_rotor.as_mut()
+    }
+
     /// Get a mutable reference to the enigma key's left rotor.
     pub fn left_rotor_mut(&mut self) -> &mut Rotor {
         &mut self.left_rotor
@@ -209,41 +823,92 @@ impl EnigmaKey {
 }
 
 impl Display for EnigmaKey {
+    /// Prints the key as it would appear on a key sheet, such as `II V III / 8 5 20 / 13 3 21 / AF TV`,
+    /// with the rotors from left to right, then their positions and ring settings numbered from 1,
+    /// then the plugs. An M4 key has the Greek wheel first, and the plugs are left off if there are
+    /// none.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Key")
-            .field(
-                "Left Rotor",
-                &format_args!(
-                    "{:?} {} {}",
-                    self.left_rotor.id,
-                    self.left_rotor.rotor_position,
-                    self.left_rotor.ring_setting
-                ),
-            )
-            .field(
-                "Middle Rotor",
-                &format_args!(
-                    "{:?} {} {}",
-                    self.middle_rotor.id,
-                    self.middle_rotor.rotor_position,
-                    self.middle_rotor.ring_setting
-                ),
-            )
-            .field(
-                "Right Rotor",
-                &format_args!(
-                    "{:?} {} {}",
-                    self.right_rotor.id,
-                    self.right_rotor.rotor_position,
-                    self.right_rotor.ring_setting
-                ),
-            )
-            .field("Plugboard", &format_args!("{}", self.plugboard))
-            .finish()
+        let rotors = self.greek_rotor.iter().chain([
+            &self.left_rotor,
+            &self.middle_rotor,
+            &self.right_rotor,
+        ]);
+
+        let mut sep = "";
+        for rotor in rotors.clone() {
+            write!(f, "{}{}", sep, rotor.id)?;
+            sep = " ";
+        }
+
+        sep = " / ";
+        for rotor in rotors.clone() {
+            write!(f, "{}{}", sep, rotor.rotor_position + 1)?;
+            sep = " ";
+        }
+
+        sep = " / ";
+        for rotor in rotors {
+            write!(f, "{}{}", sep, rotor.ring_setting + 1)?;
+            sep = " ";
+        }
+
+        let plugboard = self.plugboard.to_string();
+        if !plugboard.is_empty() {
+            write!(f, " / {}", plugboard)?;
+        }
+
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl FromStr for EnigmaKey {
+    type Err = EnigmaError;
+
+    /// Parses the format written by `Display`, which prints the parsed key back out unchanged.
+    /// Positions and ring settings can also be given as letters, such as
+    /// `II V III / H E T / M C U / AF TV`, and names and letters are read in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || EnigmaError::InvalidKey(s.to_owned());
+
+        let mut sections = s.split('/');
+        let (rotors, positions, rings) = match (sections.next(), sections.next(), sections.next()) {
+            (Some(rotors), Some(positions), Some(rings)) => (rotors, positions, rings),
+            _ => return Err(invalid()),
+        };
+        let plugboard = match (sections.next(), sections.next()) {
+            (Some(plugs), None) => plugs.parse()?,
+            (None, _) => Plugboard::new(&[]),
+            _ => return Err(invalid()),
+        };
+
+        let rotors = rotors.split_whitespace().collect::<Vec<_>>();
+        let positions = positions.split_whitespace().collect::<Vec<_>>();
+        let rings = rings.split_whitespace().collect::<Vec<_>>();
+        if !matches!(rotors.len(), 3 | 4)
+            || positions.len() != rotors.len()
+            || rings.len() != rotors.len()
+        {
+            return Err(invalid());
+        }
+
+        let mut parsed = Vec::with_capacity(rotors.len());
+        for ((id, position), ring) in rotors.iter().zip(&positions).zip(&rings) {
+            parsed.push(Rotor::try_new(
+                id.parse()?,
+                parse_setting(position)?,
+                parse_setting(ring)?,
+            )?);
+        }
+
+        Ok(match parsed[..] {
+            [greek, left, middle, right] => Self::new_m4(greek, left, middle, right, plugboard),
+            [left, middle, right] => Self::new(left, middle, right, plugboard),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rotor {
     id: RotorId,
     rotor_position: u8,
@@ -252,27 +917,52 @@ pub struct Rotor {
 
 impl Rotor {
     pub fn new(id: RotorId, rotor_position: u8, ring_setting: u8) -> Self {
-        assert!((0..26).contains(&rotor_position));
-        assert!((0..26).contains(&ring_setting));
+        Self::try_new(id, rotor_position, ring_setting).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        Self {
+    pub fn try_new(id: RotorId, rotor_position: u8, ring_setting: u8) -> Result<Self, EnigmaError> {
+        check_position(rotor_position)?;
+        check_ring_setting(ring_setting)?;
+
+        Ok(Self {
             id,
             rotor_position,
             ring_setting,
-        }
+        })
     }
 
-    fn is_at_notch(&self) -> bool {
+    /// Returns true if the rotor's current position will drive the next rotor.
+    pub fn is_at_notch(&self) -> bool {
         self.id.is_at_notch(self.rotor_position)
     }
 
-    fn turnover(&mut self) {
+    /// Steps the rotor on to its next position.
+    pub fn turnover(&mut self) {
         self.rotor_position = match self.rotor_position + 1 {
             v @ 0..=25 => v,
             v => v - 26,
         };
     }
 
+    /// Steps the rotor on `steps` times at once, returning how many of those steps started at a notch,
+    /// which is how many times it would have driven the next rotor.
+    fn advance(&mut self, steps: u64) -> u64 {
+        let partial = (steps % 26) as u8;
+        let carries = (0..partial)
+            .filter(|i| self.id.is_at_notch((self.rotor_position + i) % 26))
+            .count() as u64;
+
+        self.rotor_position = (self.rotor_position + partial) % 26;
+        steps / 26 * self.id.notch_count() as u64 + carries
+    }
+
+    /// The number of steps until the rotor reaches a notch, or `None` if it has no notches.
+    fn steps_to_notch(&self) -> Option<u64> {
+        (0..26)
+            .find(|i| self.id.is_at_notch((self.rotor_position + i) % 26))
+            .map(u64::from)
+    }
+
     // This is the hottest of hot functions. In the video example, I calculated it gets run somewhere
     // in the region of 500 million times.
     /// Requires that `c`, `ring` and `pos` are in the range 0..26.
@@ -286,7 +976,7 @@ impl Rotor {
         // The two modulo instructions have a fairly high cost, and this is the hottest
         // of hot functions in this program.
         let shift = match pos.overflowing_sub(ring) {
-            (x, true) => x + 26,
+            (x, true) => x.wrapping_add(26),
             (x, false) => x,
         };
         let idx = match c + shift {
@@ -296,11 +986,16 @@ impl Rotor {
 
         let val = mapping[idx as usize];
         match val.overflowing_sub(shift) {
-            (x, true) => x + 26,
+            (x, true) => x.wrapping_add(26),
             (x, false) => x,
         }
     }
 
+    /// How far the wiring is turned relative to the contacts.
+    fn offset(&self) -> u8 {
+        (self.rotor_position + 26 - self.ring_setting) % 26
+    }
+
     /// Assumes that `c` is in the range 0..26.
     fn forward(&self, c: u8) -> u8 {
         Self::encypher(
@@ -338,26 +1033,109 @@ impl Rotor {
 
     /// Set the rotor's rotor position.
     pub fn set_rotor_position(&mut self, rotor_position: u8) {
-        assert!((0..26).contains(&rotor_position));
+        self.try_set_rotor_position(rotor_position)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Set the rotor's rotor position, if it's in the range 0..26.
+    pub fn try_set_rotor_position(&mut self, rotor_position: u8) -> Result<(), EnigmaError> {
+        check_position(rotor_position)?;
         self.rotor_position = rotor_position;
+        Ok(())
     }
 
     /// Set the rotor's ring setting.
     pub fn set_ring_setting(&mut self, ring_setting: u8) {
-        assert!((0..26).contains(&ring_setting));
+        self.try_set_ring_setting(ring_setting)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Set the rotor's ring setting, if it's in the range 0..26.
+    pub fn try_set_ring_setting(&mut self, ring_setting: u8) -> Result<(), EnigmaError> {
+        check_ring_setting(ring_setting)?;
         self.ring_setting = ring_setting;
+        Ok(())
+    }
+}
+
+impl Display for Rotor {
+    /// Prints the rotor, position and ring setting, such as `II 8 13`. Like on a key sheet, the
+    /// position and ring setting are numbered from 1.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.id,
+            self.rotor_position + 1,
+            self.ring_setting + 1
+        )
+    }
+}
+
+impl FromStr for Rotor {
+    type Err = EnigmaError;
+
+    /// Parses the format written by `Display`. The position and ring setting can also be given as
+    /// letters, such as `II H M`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(position), Some(ring), None) => {
+                Self::try_new(id.parse()?, parse_setting(position)?, parse_setting(ring)?)
+            }
+            _ => Err(EnigmaError::InvalidKey(s.to_owned())),
+        }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Plugboard {
     wiring: [u8; 26],
+    // The path back out of the machine. Only differs from the wiring when the Uhr is fitted.
+    inverse: [u8; 26],
+    // The plugged letters in the order they were given, two to a pair, so they print back the same.
+    connections: [u8; 26],
+    connection_count: u8,
+    uhr: Option<Uhr>,
 }
 
 impl Plugboard {
     pub fn new(connections: &[(char, char)]) -> Self {
+        Self::try_new(connections).unwrap_or_else(|e| panic!("Plugboard init error: {}", e))
+    }
+
+    /// Positions in the error are counted as if the pairs were written out as a string of letters.
+    pub fn try_new(connections: &[(char, char)]) -> Result<Self, EnigmaError> {
+        let wiring = Self::decode_plugboard(connections)?;
+
+        let mut letters = [0; 26];
+        for (pair, &(a, b)) in letters.chunks_exact_mut(2).zip(connections) {
+            pair.copy_from_slice(&[a as u8 - b'A', b as u8 - b'A']);
+        }
+
+        Ok(Self {
+            wiring,
+            inverse: wiring,
+            connections: letters,
+            connection_count: connections.len() as u8,
+            uhr: None,
+        })
+    }
+
+    /// A plugboard with the Uhr switch box fitted in place of the cables.
+    pub fn with_uhr(uhr: Uhr) -> Self {
+        let wiring = uhr.wiring();
+        let mut inverse = [0; 26];
+        for (i, w) in wiring.iter().enumerate() {
+            inverse[*w as usize] = i as u8;
+        }
+
         Self {
-            wiring: Self::decode_plugboard(connections),
+            wiring,
+            inverse,
+            connections: [0; 26],
+            connection_count: 0,
+            uhr: Some(uhr),
         }
     }
 
@@ -368,53 +1146,63 @@ impl Plugboard {
         wiring
     }
 
-    fn decode_plugboard(connections: &[(char, char)]) -> [u8; 26] {
+    fn decode_plugboard(connections: &[(char, char)]) -> Result<[u8; 26], EnigmaError> {
         let mut mapping = Self::identity();
 
         if connections.is_empty() {
-            return mapping;
+            return Ok(mapping);
         }
 
         // No need for fancy hashsets, we're doing ASCII!
         let mut seen = [false; 26];
 
-        for (rc1, rc2) in connections {
-            if !rc1.is_ascii_uppercase() || !rc2.is_ascii_uppercase() {
-                panic!(
-                    "Plugboard init error: Invalid character pair: ({:?}, {:?})",
-                    rc1, rc2
-                );
+        for (i, &(rc1, rc2)) in connections.iter().enumerate() {
+            for (position, c) in [(i * 2, rc1), (i * 2 + 1, rc2)] {
+                if !c.is_ascii_uppercase() {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position,
+                        character: c,
+                    });
+                }
+
+                let l = c as u8 - b'A';
+                if seen[l as usize] {
+                    return Err(EnigmaError::DuplicateLetter {
+                        position,
+                        letter: c,
+                    });
+                }
+                seen[l as usize] = true;
             }
 
-            let c1 = *rc1 as u8 - b'A';
-            let c2 = *rc2 as u8 - b'A';
-
-            if seen[c1 as usize] || seen[c2 as usize] {
-                panic!(
-                    "Plugboard init error: Duplicate plug: ({:?}, {:?})",
-                    rc1, rc2
-                );
-            }
-
-            seen[c1 as usize] = true;
-            seen[c2 as usize] = true;
+            let c1 = rc1 as u8 - b'A';
+            let c2 = rc2 as u8 - b'A';
 
             mapping[c1 as usize] = c2;
             mapping[c2 as usize] = c1;
         }
 
-        mapping
+        Ok(mapping)
     }
 
     fn forward(&self, c: u8) -> u8 {
         self.wiring[c as usize]
     }
 
-    /// Get a reference to the plugboard's wiring.
+    fn backward(&self, c: u8) -> u8 {
+        self.inverse[c as usize]
+    }
+
+    /// Get a reference to the plugboard's wiring, on the way into the machine.
     pub fn wiring(&self) -> &[u8; 26] {
         &self.wiring
     }
 
+    /// Get a reference to the plugboard's Uhr, if it has one fitted.
+    pub fn uhr(&self) -> Option<&Uhr> {
+        self.uhr.as_ref()
+    }
+
     /// Return value is true if unplugged.
     pub fn unplugged(&self) -> [bool; 26] {
         let mut ret_val = [true; 26];
@@ -432,126 +1220,680 @@ impl Plugboard {
     /// to create and copy an EnigmaKey, whereas Mike Pound stored his encoded as a String,
     /// making it easier for his implementation to get the current plugboard.
     /// This function is only called like 10 times, so the cost isn't too bad.
+    /// With the Uhr fitted, these are its cables' plug pairs instead.
     pub fn generate_connections(&self) -> Vec<(char, char)> {
-        let mut connections = Vec::new();
+        if let Some(uhr) = &self.uhr {
+            return uhr.pairs();
+        }
 
-        let mut seen = [false; 26];
-        for (idx, other) in self.wiring.iter().enumerate() {
-            if idx as u8 == *other || seen[idx] {
-                continue; // Not connected or already seen.
+        self.connections[..self.connection_count as usize * 2]
+            .chunks_exact(2)
+            .map(|pair| ((pair[0] + b'A') as char, (pair[1] + b'A') as char))
+            .collect()
+    }
+}
+
+impl TryFrom<&[(char, char)]> for Plugboard {
+    type Error = EnigmaError;
+
+    fn try_from(connections: &[(char, char)]) -> Result<Self, Self::Error> {
+        Self::try_new(connections)
+    }
+}
+
+impl FromStr for Plugboard {
+    type Err = EnigmaError;
+
+    /// Parses whitespace separated plug pairs, such as `AF TV KO BL RW`, or an Uhr in the form
+    /// written by its `Display`. Letters can be in either case, like rotor names. Positions in the
+    /// error are counted from the first pair, as if the pairs were written out as a string of letters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (uhr_dial, plugs) = match s.trim_start().strip_prefix("Uhr") {
+            Some(rest) => {
+                let (dial, plugs) = rest
+                    .split_once(':')
+                    .ok_or_else(|| EnigmaError::InvalidKey(s.to_owned()))?;
+                let dial = dial
+                    .trim()
+                    .parse::<u8>()
+                    .map_err(|_| EnigmaError::InvalidSetting(dial.trim().to_owned()))?;
+                (Some(dial), plugs)
             }
+            None => (None, s),
+        };
 
-            seen[idx] = true;
-            seen[*other as usize] = true;
+        let mut connections = Vec::new();
+        for (i, pair) in plugs.split_whitespace().enumerate() {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(a), Some(b), None) => {
+                    connections.push((a.to_ascii_uppercase(), b.to_ascii_uppercase()))
+                }
+                (_, _, Some(c)) => {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position: i * 2 + 2,
+                        character: c,
+                    })
+                }
+                _ => return Err(EnigmaError::InvalidKey(s.to_owned())),
+            }
+        }
 
-            let a = (idx as u8 + b'A') as char;
-            let b = (*other + b'A') as char;
-            connections.push((a, b));
+        match uhr_dial {
+            Some(dial) => Ok(Self::with_uhr(Uhr::new(&connections, dial)?)),
+            None => Self::try_new(&connections),
         }
+    }
+}
 
-        connections
+impl From<Uhr> for Plugboard {
+    fn from(uhr: Uhr) -> Self {
+        Self::with_uhr(uhr)
     }
 }
 
 impl Display for Plugboard {
+    /// Prints the plug pairs in the order they were given, such as `AF TV KO BL RW`, so a parsed
+    /// plugboard prints back as it was written.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut seen = [false; 26];
+        if let Some(uhr) = &self.uhr {
+            return Display::fmt(uhr, f);
+        }
 
-        let mut wiring_iter = self
-            .wiring
-            .iter()
-            .enumerate()
-            .filter(|(i, other)| *i != **other as usize);
+        let mut sep = "";
+        for (a, b) in self.generate_connections() {
+            write!(f, "{}{}{}", sep, a, b)?;
+            sep = " ";
+        }
 
-        if let Some((idx, other)) = wiring_iter.next() {
-            seen[idx] = true;
-            seen[*other as usize] = true;
+        Ok(())
+    }
+}
 
-            let a = (idx as u8 + b'A') as char;
-            let b = (*other + b'A') as char;
+/// Where a machine is part way through a message. This is its key with the rotors wherever the last
+/// key press left them, along with the reflector, which moves on the Enigma G.
+#[derive(Debug, Clone, Copy)]
+pub struct EnigmaState {
+    key: EnigmaKey,
+    reflector: Reflector,
+}
 
-            f.write_char(a)?;
-            f.write_char(b)?;
-        }
+impl Deref for EnigmaState {
+    type Target = EnigmaKey;
 
-        for (idx, other) in wiring_iter {
-            if seen[idx] {
-                continue; // We can ignore the other half as they always come in pairs.
-            }
+    fn deref(&self) -> &Self::Target {
+        &self.key
+    }
+}
 
-            seen[idx] = true;
-            seen[*other as usize] = true;
+impl EnigmaState {
+    /// Get a reference to the key, with the rotors at their current positions.
+    pub fn key(&self) -> &EnigmaKey {
+        &self.key
+    }
 
-            let a = (idx as u8 + b'A') as char;
-            let b = (*other + b'A') as char;
+    /// Get a reference to the reflector, at its current position.
+    pub fn reflector(&self) -> &Reflector {
+        &self.reflector
+    }
+}
 
-            f.write_char(' ')?;
-            f.write_char(a)?;
-            f.write_char(b)?;
-        }
+/// The classic three-rotor machine, plus the M4's Greek wheel. This is a thin wrapper around a
+/// three-rotor `Machine` which takes its settings from an `EnigmaKey`.
+pub struct Enigma<S = Ratchet> {
+    machine: Machine<3, S>,
+}
 
-        Ok(())
+impl Enigma<Ratchet> {
+    pub fn new(key: EnigmaKey, reflector: impl Into<Reflector>) -> Self {
+        Self::with_stepping(key, reflector, Ratchet)
     }
 }
 
-pub struct Enigma {
-    left_rotor: Rotor,
-    middle_rotor: Rotor,
-    right_rotor: Rotor,
-    reflector: ReflectorId,
-    plugboard: Plugboard,
+impl Enigma<Cog> {
+    /// Creates an Abwehr Enigma G, which has the commercial entry wheel and cog-wheel stepping.
+    /// It should be fitted with one of the G rotor sets and its matching reflector.
+    pub fn new_g(key: EnigmaKey, reflector: impl Into<Reflector>) -> Self {
+        let mut enigma = Self::with_stepping(key, reflector, Cog);
+        enigma.set_entry_wheel(EntryWheel::QWERTZU);
+
+        enigma
+    }
 }
 
-impl Enigma {
-    pub fn new(key: EnigmaKey, reflector: ReflectorId) -> Self {
+impl<S: Stepping> Enigma<S> {
+    pub fn with_stepping(key: EnigmaKey, reflector: impl Into<Reflector>, stepping: S) -> Self {
+        let mut reflector = reflector.into();
+        if key.greek_rotor.is_some() {
+            reflector.set_greek_rotor(key.greek_rotor);
+        }
+
+        let rotors = [key.left_rotor, key.middle_rotor, key.right_rotor];
         Self {
-            left_rotor: key.left_rotor,
-            middle_rotor: key.middle_rotor,
-            right_rotor: key.right_rotor,
+            machine: Machine::new(rotors, reflector, key.plugboard, stepping),
+        }
+    }
+
+    /// Get a reference to the underlying three-rotor machine.
+    pub fn machine(&self) -> &Machine<3, S> {
+        &self.machine
+    }
+
+    /// Set the machine's entry wheel. This defaults to the identity wheel of the military machines.
+    pub fn set_entry_wheel(&mut self, entry_wheel: EntryWheel) {
+        self.machine.set_entry_wheel(entry_wheel);
+    }
+
+    /// Returns where the machine currently is, with the rotors and reflector wherever the last key
+    /// press left them. A new `Enigma` made from its key and reflector carries on where this one is.
+    pub fn state(&self) -> EnigmaState {
+        let [left_rotor, middle_rotor, right_rotor] = *self.machine.rotors();
+        EnigmaState {
+            key: EnigmaKey {
+                greek_rotor: self.machine.reflector().greek_rotor,
+                left_rotor,
+                middle_rotor,
+                right_rotor,
+                plugboard: *self.machine.plugboard(),
+            },
+            reflector: *self.machine.reflector(),
+        }
+    }
+
+    /// Get the number of keys pressed since the machine was set up.
+    pub fn offset(&self) -> u64 {
+        self.machine.offset()
+    }
+
+    /// Moves the rotors to where they'd be after `offset` key presses from the key the machine was
+    /// set up with, so a message can be picked up part way through.
+    pub fn seek(&mut self, offset: u64) {
+        self.machine.seek(offset);
+    }
+
+    /// Undoes the last key press. Panics if no keys have been pressed.
+    pub fn step_back(&mut self) {
+        self.machine.step_back();
+    }
+
+    pub fn encrypt(&mut self, c: char) -> char {
+        self.machine.encrypt(c)
+    }
+
+    /// Like `encrypt`, but returns an error instead of panicking if `c` isn't an uppercase ASCII
+    /// letter. A single letter has no position, so it's always reported at position 0.
+    pub fn try_encrypt(&mut self, c: char) -> Result<char, EnigmaError> {
+        self.machine.try_encrypt(c)
+    }
+
+    /// Encrypts a whole message, checking it's all uppercase ASCII letters before the machine is
+    /// stepped, so the machine is left untouched on error.
+    pub fn try_encrypt_str(&mut self, text: &str) -> Result<String, EnigmaError> {
+        self.machine.try_encrypt_str(text)
+    }
+
+    /// Encrypts letters in the machine's internal alphabet, where A is 0 and Z is 25, overwriting
+    /// them. See `to_letters` for converting text.
+    pub fn encrypt_in_place(&mut self, letters: &mut [u8]) -> Result<(), EnigmaError> {
+        self.machine.encrypt_in_place(letters)
+    }
+
+    /// Like `encrypt_in_place`, but appends the encrypted letters to `out`.
+    pub fn encrypt_into(&mut self, letters: &[u8], out: &mut Vec<u8>) -> Result<(), EnigmaError> {
+        self.machine.encrypt_into(letters, out)
+    }
+
+    /// Like `encrypt`, but records the letter at every stage of the signal path. This is much slower
+    /// than `encrypt`, so is meant for showing how the machine works. Returns an error if `c` isn't an
+    /// uppercase ASCII letter.
+    pub fn encrypt_traced(&mut self, c: char) -> Result<Trace, EnigmaError> {
+        self.machine.encrypt_traced(c)
+    }
+
+    /// Draws the machine's wiring at its current position as an SVG diagram. If a trace from
+    /// `encrypt_traced` is given, its signal path is highlighted, so it should be the last key pressed.
+    pub fn to_svg(&self, trace: Option<&Trace>) -> String {
+        self.machine.to_svg(trace)
+    }
+
+    /// Returns the permutation the whole machine applies at the rotors' current position, plugboard
+    /// included. The rotors step before each key press, so this is what the last key was encrypted
+    /// with.
+    pub fn permutation(&self) -> Permutation {
+        self.machine.permutation()
+    }
+
+    /// Records the scrambler for each of the next `len` key presses, so the same rotor setting can
+    /// be tried with many plugboards. This machine isn't moved.
+    pub fn scrambler_tables(&self, len: usize) -> ScramblerTables
+    where
+        S: Clone,
+    {
+        self.machine.scrambler_tables(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = "ABWEHRSTELLEHAMBURGMELDETANKUNFTDESAGENTENINLISSABONXX";
+
+    fn letters(positions: &[u8]) -> String {
+        positions.iter().map(|&p| (p + b'A') as char).collect()
+    }
+
+    fn g_machine(
+        rotors: [RotorId; 3],
+        positions: [u8; 3],
+        ring_settings: [u8; 3],
+        reflector: Reflector,
+    ) -> Enigma<Cog> {
+        let [left, middle, right] =
+            std::array::from_fn(|i| Rotor::new(rotors[i], positions[i], ring_settings[i]));
+        Enigma::new_g(
+            EnigmaKey::new(left, middle, right, Plugboard::new(&[])),
             reflector,
-            plugboard: key.plugboard,
+        )
+    }
+
+    fn positions(enigma: &Enigma<Cog>) -> String {
+        let rotors: Vec<_> = enigma
+            .machine()
+            .rotors()
+            .iter()
+            .map(Rotor::rotor_position)
+            .collect();
+        format!(
+            "{} {}",
+            letters(&rotors),
+            letters(&[enigma.machine().reflector().position()])
+        )
+    }
+
+    // The U-264 message of 25 November 1942, first broken by the M4 Project in 2006.
+    #[test]
+    fn m4_message() {
+        let key: EnigmaKey = "Beta II IV I / V J N A / A A A V / AT BL DF GJ HM NW OP QY RZ VX"
+            .parse()
+            .unwrap();
+        let mut enigma = Enigma::new(key, ReflectorId::BThin);
+        let cipher = "NCZWVUSXPNYMINHZXMQXSFWXWLKJAHSHNMCOCCAKUQPMKCSMHKSEINJUSBLKIOSXCKUBHMLLXCSJUSRRDV\
+                      KOHULXWCCBGVLIYXEOAHXRHKKFVDREWEZLXOBAFGYUJQUKGRTVUKAMEURBVEKSUHHVOYHABCJWMAKLFK\
+                      LMYFVNRIZRVVRTKOFDANJMOLBGFFLEOPRGTFLVRHOWOPBEKVWMUQFMPWPARMFHAGKXIIBG";
+        let plain = "VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXXBEIANGRIFFUNTERWASSERGEDRUE\
+                     CKTYWABOSXLETZTERGEGNERSTANDNULACHTDREINULUHRMARQUANTONJOTANEUNACHTSEYHSDREIYZWO\
+                     ZWONULGRADYACHTSMYSTOSSENACHXEKNSVIERMBFAELLTYNNNNNNOOOVIERYSICHTEINSNULL";
+        assert_eq!(enigma.try_encrypt_str(cipher).unwrap(), plain);
+
+        // The Greek wheel never moves, however far the other rotors go.
+        assert_eq!(enigma.state().greek_rotor().unwrap().rotor_position(), 21);
+    }
+
+    #[test]
+    fn custom_wiring_is_checked() {
+        assert_eq!(
+            RotorId::custom("Short", "EKMFLGDQVZNTOWYHXUSPAIBRC", &[16]),
+            Err(EnigmaError::InvalidLength(25))
+        );
+        assert_eq!(
+            RotorId::custom("Repeated", "EKMFLGDQVZNTOWYHXUSPAIBRCE", &[16]),
+            Err(EnigmaError::DuplicateLetter {
+                position: 25,
+                letter: 'E'
+            })
+        );
+        assert_eq!(
+            RotorId::custom("Lowercase", "eKMFLGDQVZNTOWYHXUSPAIBRCJ", &[16]),
+            Err(EnigmaError::InvalidCharacter {
+                position: 0,
+                character: 'e'
+            })
+        );
+        assert_eq!(
+            RotorId::custom("Notch", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", &[16, 26]),
+            Err(EnigmaError::InvalidNotch {
+                position: 1,
+                notch: 26
+            })
+        );
+        assert_eq!(
+            RotorId::custom("i", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", &[16]),
+            Err(EnigmaError::ReservedName("i".to_owned()))
+        );
+
+        // A reflector can't leave a letter where it is, and must pair letters both ways.
+        assert_eq!(
+            ReflectorId::custom("Fixed point", "AYXWVUTSRQPONMLKJIHGFEDCBZ"),
+            Err(EnigmaError::NotInvolution {
+                letter: 'A',
+                mapped: 'A'
+            })
+        );
+        assert_eq!(
+            ReflectorId::custom("One way", "BCAWVUTSRQPONMLKJIHGFEDZYX"),
+            Err(EnigmaError::NotInvolution {
+                letter: 'A',
+                mapped: 'B'
+            })
+        );
+        assert_eq!(
+            ReflectorId::custom("Short", "YRUHQSLDPXNGOKMIEBFZCWVJA"),
+            Err(EnigmaError::InvalidLength(25))
+        );
+    }
+
+    #[test]
+    fn custom_parts_match_built_in() {
+        let rotor = RotorId::custom("My I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", &[16]).unwrap();
+        let reflector = ReflectorId::custom("My B", "YRUHQSLDPXNGOKMIEBFZCWVJAT").unwrap();
+        assert_eq!(rotor.name(), "My I");
+
+        // Rotor I is on the right, so its notch is what drives the middle rotor.
+        let machine = |right, reflector| {
+            let key = EnigmaKey::new(
+                Rotor::new(RotorId::II, 3, 7),
+                Rotor::new(RotorId::III, 21, 2),
+                Rotor::new(right, 11, 19),
+                Plugboard::new(&[('A', 'F'), ('T', 'V')]),
+            );
+            Enigma::new(key, reflector)
+        };
+        let text = "THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG".repeat(3);
+        assert_eq!(
+            machine(rotor, reflector).try_encrypt_str(&text).unwrap(),
+            machine(RotorId::I, ReflectorId::B)
+                .try_encrypt_str(&text)
+                .unwrap()
+        );
+    }
+
+    // No published G-312 or G-260 ciphertext could be found to test against, so these are regression
+    // vectors: they record this crate's own output and only catch changes to it. Each one carries into
+    // the reflector part way through.
+    #[test]
+    fn g312_messages() {
+        let ids = [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III];
+        let cases = [
+            (
+                [0, 1, 2],
+                *b"AAA",
+                *b"AAA",
+                (b'A', b'A'),
+                "EAFCDWRSCGVJLJTZJALBPMELSKHYSWTJVUMJMMYRNPNVUFVHHNYLVN",
+                "NXC J",
+            ),
+            (
+                [2, 0, 1],
+                *b"QMF",
+                *b"CXK",
+                (b'R', b'F'),
+                "RHNFBZPKZSTCUPJCMLUVBUEPDFIERTWMMHOLBGVJPECBRYTQOVAESP",
+                "KSH Z",
+            ),
+        ];
+
+        for (order, start, rings, (position, ring), cipher, end) in cases {
+            let mut enigma = g_machine(
+                order.map(|i| ids[i]),
+                start.map(|c| c - b'A'),
+                rings.map(|c| c - b'A'),
+                Reflector::new(ReflectorId::G312_UKW, position - b'A', ring - b'A'),
+            );
+            assert_eq!(enigma.try_encrypt_str(cipher).unwrap(), PLAINTEXT);
+            assert_eq!(positions(&enigma), end);
         }
     }
 
-    fn rotate(&mut self) {
-        // If middle rotor notch - double-stepping
-        if self.middle_rotor.is_at_notch() {
-            self.middle_rotor.turnover();
-            self.left_rotor.turnover();
+    #[test]
+    fn g260_message() {
+        let mut enigma = g_machine(
+            [RotorId::G260_II, RotorId::G260_III, RotorId::G260_I],
+            [25, 25, 24],
+            [9, 3, 22],
+            Reflector::new(ReflectorId::G260_UKW, 24, 1),
+        );
+        let cipher = "HSCLXWMRLDPDQFBUTZSKLBWQNMTWLUTVKMFRYOLMJLSDJQAMVMXRWU";
+        assert_eq!(enigma.try_encrypt_str(cipher).unwrap(), PLAINTEXT);
+        assert_eq!(positions(&enigma), "OIA I");
+    }
+
+    #[test]
+    fn cog_carries_into_reflector() {
+        // Every rotor is at one of its notches, so the carry goes all the way through.
+        let mut enigma = g_machine(
+            [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III],
+            [18, 18, 20],
+            [0; 3],
+            ReflectorId::G312_UKW.into(),
+        );
+        enigma.encrypt('A');
+        assert_eq!(positions(&enigma), "TTV B");
+
+        // The left rotor isn't at a notch, so the reflector stays put.
+        let mut enigma = g_machine(
+            [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III],
+            [17, 18, 20],
+            [0; 3],
+            ReflectorId::G312_UKW.into(),
+        );
+        enigma.encrypt('A');
+        assert_eq!(positions(&enigma), "STV A");
+    }
+
+    #[test]
+    fn state_includes_reflector() {
+        let make = || {
+            g_machine(
+                [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III],
+                [18, 18, 20],
+                [0; 3],
+                ReflectorId::G312_UKW.into(),
+            )
+        };
+
+        let mut enigma = make();
+        enigma.try_encrypt_str("ABWEHR").unwrap();
+        let state = enigma.state();
+        assert_eq!(state.reflector().position(), 1);
+
+        let mut restored = Enigma::new_g(*state.key(), *state.reflector());
+        let mut original = make();
+        original.seek(6);
+        assert_eq!(
+            restored.try_encrypt_str("STELLEHAMBURG").unwrap(),
+            original.try_encrypt_str("STELLEHAMBURG").unwrap()
+        );
+    }
+
+    #[test]
+    fn ratchet_seek_skips_whole_loops() {
+        // The middle rotor starts on one of its notches, so it double-steps on the first key press
+        // and the rotors never come back to where they started.
+        let key: EnigmaKey = "VI VII III / 1 13 6 / 3 14 7".parse().unwrap();
+        let rotors = |enigma: &Enigma| enigma.machine().rotors().map(|r| r.rotor_position());
+
+        let mut stepped = Enigma::new(key, ReflectorId::B);
+        let mut history = vec![rotors(&stepped)];
+        for _ in 0..40_000 {
+            stepped.encrypt('A');
+            history.push(rotors(&stepped));
         }
-        // If left-rotor notch
-        else if self.right_rotor.is_at_notch() {
-            self.middle_rotor.turnover();
+
+        let mut sought = Enigma::new(key, ReflectorId::B);
+        for &offset in &[39_999, 17_576, 16_900, 33_800, 27, 1] {
+            sought.seek(offset);
+            assert_eq!(rotors(&sought), history[offset as usize]);
         }
+    }
 
-        self.right_rotor.turnover();
+    #[test]
+    fn multi_notch_carries() {
+        // G312-III has notches at U, W and X, but not T or V.
+        let mut enigma = g_machine(
+            [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III],
+            [1, 1, 19],
+            [0; 3],
+            ReflectorId::G312_UKW.into(),
+        );
+        let middle: String = (0..5)
+            .map(|_| {
+                enigma.encrypt('A');
+                (enigma.state().middle_rotor().rotor_position() + b'A') as char
+            })
+            .collect();
+        assert_eq!(middle, "BCCDE");
+
+        // Over a full turn, the right rotor carries once for each of its 11 notches, and the middle
+        // and left rotors carry on from there. Seeking must agree with typing.
+        let machine = || {
+            g_machine(
+                [RotorId::G312_I, RotorId::G312_II, RotorId::G312_III],
+                [1, 1, 0],
+                [0; 3],
+                ReflectorId::G312_UKW.into(),
+            )
+        };
+        let (mut typed, mut seeked) = (machine(), machine());
+        for _ in 0..26 {
+            typed.encrypt('A');
+        }
+        seeked.seek(26);
+        assert_eq!(positions(&typed), "HMA F");
+        assert_eq!(positions(&seeked), positions(&typed));
     }
 
-    pub fn encrypt(&mut self, c: char) -> char {
-        assert!(c.is_ascii_uppercase());
-        let mut c = c as u8 - b'A';
+    #[test]
+    fn built_in_reflectors_are_involutions() {
+        for id in ReflectorId::BUILT_IN {
+            for (c, &w) in id.wiring().iter().enumerate() {
+                assert_ne!(w as usize, c, "{}", id);
+                assert_eq!(id.wiring()[w as usize] as usize, c, "{}", id);
+            }
+        }
+    }
 
-        self.rotate();
+    #[test]
+    fn key_string_round_trips() {
+        for s in [
+            "II V III / 8 5 20 / 13 3 21 / AF TV KO BL RW",
+            "I II III / 1 1 1 / 1 1 1",
+            "Beta II IV I / 22 10 14 1 / 1 1 1 22 / AT BL DF GJ HM NW OP QY RZ VX",
+            "I IV III / 16 26 8 / 13 1 12 / Uhr 27: AT BL DF GJ HM NW OP QY RZ VX",
+            "D-III D-I D-II / 1 2 3 / 4 5 6",
+        ] {
+            let key: EnigmaKey = s.parse().unwrap();
+            assert_eq!(key.to_string(), s);
+        }
 
-        // Plugboard in
-        c = self.plugboard.forward(c);
+        // Plug pairs keep the order they were given in, and the order of their letters.
+        let plugboard: Plugboard = "ZA TV BL".parse().unwrap();
+        assert_eq!(plugboard.to_string(), "ZA TV BL");
+        assert_eq!(
+            plugboard.generate_connections(),
+            [('Z', 'A'), ('T', 'V'), ('B', 'L')]
+        );
+    }
 
-        // Right to left
-        c = self.right_rotor.forward(c);
-        c = self.middle_rotor.forward(c);
-        c = self.left_rotor.forward(c);
+    #[test]
+    fn key_string_is_case_insensitive() {
+        let key: EnigmaKey = "ii v iii / h e t / m c u / af tv".parse().unwrap();
+        assert_eq!(key.to_string(), "II V III / 8 5 20 / 13 3 21 / AF TV");
+        assert_eq!(
+            "II V III / H E T / M C U / AF TV"
+                .parse::<EnigmaKey>()
+                .unwrap()
+                .to_string(),
+            key.to_string()
+        );
+    }
 
-        // Reflector
-        c = self.reflector.forward(c);
+    #[test]
+    fn invalid_key_strings() {
+        let parse = |s: &str| s.parse::<EnigmaKey>().map(|key| key.to_string());
+        let invalid = |s: &str| Err(EnigmaError::InvalidKey(s.to_owned()));
+
+        for s in [
+            "I II III",
+            "I II III / 1 1 1",
+            "I II / 1 1 / 1 1",
+            "I II III / 1 1 / 1 1 1",
+            "I II III / 1 1 1 / 1 1 1 / AB / CD",
+        ] {
+            assert_eq!(parse(s), invalid(s));
+        }
 
-        // Left to right
-        c = self.left_rotor.backward(c);
-        c = self.middle_rotor.backward(c);
-        c = self.right_rotor.backward(c);
+        assert_eq!(
+            parse("I II IX / 1 1 1 / 1 1 1"),
+            Err(EnigmaError::UnknownRotor("IX".to_owned()))
+        );
+        for setting in ["0", "27", "AB", "-1", "?"] {
+            assert_eq!(
+                parse(&format!("I II III / 1 {} 1 / 1 1 1", setting)),
+                Err(EnigmaError::InvalidSetting(setting.to_owned()))
+            );
+        }
+        assert_eq!(
+            parse("I II III / 1 1 1 / 1 1 1 / AB CA"),
+            Err(EnigmaError::DuplicateLetter {
+                position: 3,
+                letter: 'A'
+            })
+        );
+        assert_eq!(
+            parse("I II III / 1 1 1 / 1 1 1 / AB C1"),
+            Err(EnigmaError::InvalidCharacter {
+                position: 3,
+                character: '1'
+            })
+        );
+        assert_eq!(
+            parse("I II III / 1 1 1 / 1 1 1 / AB CDE"),
+            Err(EnigmaError::InvalidCharacter {
+                position: 4,
+                character: 'E'
+            })
+        );
+        assert_eq!(
+            parse("I II III / 1 1 1 / 1 1 1 / Uhr 40: AT BL DF GJ HM NW OP QY RZ VX"),
+            Err(EnigmaError::InvalidUhrSetting(40))
+        );
+    }
 
-        // Plugboard out
-        c = self.plugboard.forward(c);
+    #[test]
+    fn encrypt_traced_rejects_invalid_characters() {
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1".parse().unwrap();
+        let mut enigma = Enigma::new(key, ReflectorId::B);
+
+        assert!(matches!(
+            enigma.encrypt_traced('a'),
+            Err(EnigmaError::InvalidCharacter {
+                position: 0,
+                character: 'a'
+            })
+        ));
+        assert_eq!(enigma.offset(), 0);
+
+        let trace = enigma.encrypt_traced('A').unwrap();
+        assert_eq!(enigma.offset(), 1);
+        assert_eq!(
+            trace.output(),
+            Enigma::new(key, ReflectorId::B).encrypt('A')
+        );
+    }
 
-        (c + b'A') as char
+    #[test]
+    fn uhr_diagram_has_a_column_each_way() {
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1 / Uhr 27: AT BL DF GJ HM NW OP QY RZ VX"
+            .parse()
+            .unwrap();
+        let mut enigma = Enigma::new(key, ReflectorId::B);
+        let trace = enigma.encrypt_traced('A').unwrap();
+        let svg = enigma.to_svg(Some(&trace));
+        assert!(svg.contains(">Uhr in<") && svg.contains(">Uhr out<"));
+
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1 / AT BL".parse().unwrap();
+        let svg = Enigma::new(key, ReflectorId::B).to_svg(None);
+        assert!(svg.contains(">Plugboard<") && !svg.contains("Uhr"));
     }
 }
@@ -0,0 +1,364 @@
+use std::array;
+
+use crate::error::EnigmaError;
+
+use super::{
+    machine::check_letters, stepping::Stepping, EnigmaKey, EntryWheel, Plugboard, Reflector, Rotor,
+};
+
+// The number of positions the three rotors can be in together.
+const ROTOR_STATES: usize = 26 * 26 * 26;
+
+/// The rotor stack for one rotor order, precomputed so it can be shared by every `TableBatch` that
+/// only differs in where the rotors start.
+///
+/// Where the rotors are is a single number, the state, and the stepping is a table from each state
+/// to the next. The right rotor is folded in with the plugboard and entry wheel on each side, and
+/// everything to the left of it is a single table for each position of the middle and left rotors.
+/// So a key press is four lookups, with no branches, whatever the stepping.
+#[derive(Debug, Clone)]
+pub struct BatchTables {
+    rotors: [Rotor; 3],
+    reflector: Reflector,
+    plugboard: Plugboard,
+    moves_reflector: bool,
+    // Indexed by the right rotor's position.
+    input: [[u8; 26]; 26],
+    output: [[u8; 26]; 26],
+    // Indexed by the state without the right rotor, so the reflector's position if it moves, then
+    // the left and middle rotors' positions.
+    back: Vec<[u8; 26]>,
+    // The state after the next key press, for every state.
+    next: Vec<u32>,
+}
+
+impl BatchTables {
+    /// Builds the tables for the key's rotor order, ring settings and plugboard. The rotor positions
+    /// in the key are ignored, as each lane of a `TableBatch` has its own.
+    ///
+    /// If the stepping moves the reflector, it's part of the state, so the tables are 26 times the
+    /// size at about 12 MB.
+    pub fn new<S: Stepping>(key: &EnigmaKey, reflector: impl Into<Reflector>, stepping: S) -> Self {
+        let mut reflector = reflector.into();
+        if key.greek_rotor.is_some() {
+            reflector.set_greek_rotor(key.greek_rotor);
+        }
+
+        let mut tables = Self {
+            rotors: [key.left_rotor, key.middle_rotor, key.right_rotor],
+            reflector,
+            plugboard: key.plugboard,
+            moves_reflector: stepping.moves_reflector(),
+            input: [[0; 26]; 26],
+            output: [[0; 26]; 26],
+            back: Vec::new(),
+            next: Vec::new(),
+        };
+        tables.update_io_tables(EntryWheel::IDENTITY);
+
+        let states = if tables.moves_reflector {
+            ROTOR_STATES * 26
+        } else {
+            ROTOR_STATES
+        };
+        tables.back = (0..states / 26)
+            .map(|state| {
+                let (rotors, reflector) = tables.decode(state as u32 * 26);
+                Self::back_table(&rotors, &reflector)
+            })
+            .collect();
+        tables.next = (0..states as u32)
+            .map(|state| {
+                let (mut rotors, mut reflector) = tables.decode(state);
+                stepping.step(&mut rotors, &mut reflector);
+                tables.encode(&rotors, &reflector)
+            })
+            .collect();
+
+        tables
+    }
+
+    /// Set the entry wheel. This defaults to the identity wheel of the military machines.
+    pub fn set_entry_wheel(&mut self, entry_wheel: EntryWheel) {
+        self.update_io_tables(entry_wheel);
+    }
+
+    fn update_io_tables(&mut self, entry_wheel: EntryWheel) {
+        let mut right = self.rotors[2];
+        for position in 0..26 {
+            right.rotor_position = position;
+            for c in 0..26 {
+                let entered = entry_wheel.forward(self.plugboard.forward(c));
+                self.input[position as usize][c as usize] = right.forward(entered);
+
+                let exited = entry_wheel.backward(right.backward(c));
+                self.output[position as usize][c as usize] = self.plugboard.backward(exited);
+            }
+        }
+    }
+
+    fn back_table(rotors: &[Rotor; 3], reflector: &Reflector) -> [u8; 26] {
+        let [left, middle, _] = rotors;
+
+        let mut table = [0; 26];
+        for (c, t) in (0..).zip(&mut table) {
+            let reflected = reflector.forward(left.forward(middle.forward(c)));
+            *t = middle.backward(left.backward(reflected));
+        }
+
+        table
+    }
+
+    /// The state for these positions. The reflector's position only counts if it moves.
+    fn encode(&self, rotors: &[Rotor; 3], reflector: &Reflector) -> u32 {
+        let reflector = if self.moves_reflector {
+            reflector.position as u32
+        } else {
+            0
+        };
+
+        rotors.iter().fold(reflector, |state, rotor| {
+            state * 26 + rotor.rotor_position as u32
+        })
+    }
+
+    fn decode(&self, state: u32) -> ([Rotor; 3], Reflector) {
+        let mut rotors = self.rotors;
+        let mut rest = state;
+        for rotor in rotors.iter_mut().rev() {
+            rotor.rotor_position = (rest % 26) as u8;
+            rest /= 26;
+        }
+
+        let mut reflector = self.reflector;
+        if self.moves_reflector {
+            reflector.set_position(rest as u8);
+        }
+
+        (rotors, reflector)
+    }
+}
+
+/// A batched table engine, which encrypts the same letters under `L` keys side by side, one lane per
+/// key. The lanes share a `BatchTables`, so they can only differ in where the rotors start.
+///
+/// Each lane's state is a single number, and a key press runs the same four table lookups for every
+/// lane with no branches. There are no SIMD instructions for looking up bytes in tables this size, so
+/// the lookups are still made one lane at a time. The lanes don't depend on each other, so the CPU can
+/// overlap them, but that only helps a little: on one core, `examples/batch_throughput.rs` measured
+/// about 900k keys a second with one lane, 1.07M with four and 0.95M with 32, against 156k for a
+/// plain `Enigma`. Nearly all the speed comes from the tables, not from the lanes.
+pub struct TableBatch<'a, const L: usize> {
+    tables: &'a BatchTables,
+    states: [u32; L],
+}
+
+impl<'a, const L: usize> TableBatch<'a, L> {
+    /// Sets up a lane for each set of rotor positions, given from left to right.
+    pub fn new(tables: &'a BatchTables, positions: &[[u8; 3]; L]) -> Self {
+        Self {
+            tables,
+            states: array::from_fn(|lane| {
+                let mut rotors = tables.rotors;
+                for (rotor, &position) in rotors.iter_mut().zip(&positions[lane]) {
+                    rotor.set_rotor_position(position);
+                }
+                tables.encode(&rotors, &tables.reflector)
+            }),
+        }
+    }
+
+    /// Returns each lane's rotors, ordered from left to right.
+    pub fn rotors(&self) -> [[Rotor; 3]; L] {
+        self.states.map(|state| self.tables.decode(state).0)
+    }
+
+    /// Returns the key each lane is currently at.
+    pub fn keys(&self) -> [EnigmaKey; L] {
+        self.rotors()
+            .map(|[left_rotor, middle_rotor, right_rotor]| EnigmaKey {
+                greek_rotor: self.tables.reflector.greek_rotor,
+                left_rotor,
+                middle_rotor,
+                right_rotor,
+                plugboard: self.tables.plugboard,
+            })
+    }
+
+    /// Encrypts letters in the machine's internal alphabet in every lane, appending each lane's
+    /// output to its own `Vec`. The letters are checked before any lane is stepped.
+    pub fn encrypt_into(
+        &mut self,
+        letters: &[u8],
+        out: &mut [Vec<u8>; L],
+    ) -> Result<(), EnigmaError> {
+        check_letters(letters)?;
+
+        out.iter_mut().for_each(|out| out.reserve(letters.len()));
+        for &c in letters {
+            let encrypted = self.press(c);
+            for (out, &e) in out.iter_mut().zip(&encrypted) {
+                out.push(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts letters in every lane, but only counts how often each letter comes out rather than
+    /// storing the output. This is all that letter frequency tests such as the index of coincidence
+    /// need.
+    pub fn histograms(&mut self, letters: &[u8]) -> Result<[[u32; 26]; L], EnigmaError> {
+        check_letters(letters)?;
+
+        let mut histograms = [[0; 26]; L];
+        for &c in letters {
+            let encrypted = self.press(c);
+            for (histogram, &e) in histograms.iter_mut().zip(&encrypted) {
+                histogram[e as usize] += 1;
+            }
+        }
+
+        Ok(histograms)
+    }
+
+    /// Steps every lane and encrypts `c` in each. Requires that `c` is in the range 0..26.
+    fn press(&mut self, c: u8) -> [u8; L] {
+        let tables = self.tables;
+        let c = c as usize;
+
+        let mut encrypted = [0; L];
+        for (state, e) in self.states.iter_mut().zip(&mut encrypted) {
+            *state = tables.next[*state as usize];
+            let right = *state as usize % 26;
+            let back = *state as usize / 26;
+
+            let c = tables.input[right][c];
+            let c = tables.back[back][c as usize];
+            *e = tables.output[right][c as usize];
+        }
+
+        encrypted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{
+        stepping::{Cog, Ratchet},
+        to_letters, Enigma, ReflectorId,
+    };
+
+    // The lanes start just before the right, middle and left rotors turn over and on a double step,
+    // for rotors I, II and III, so every lane steps across a turnover in the first few letters.
+    const POSITIONS: [[u8; 3]; 8] = [
+        [0, 0, 0],
+        [0, 0, 20],
+        [0, 3, 21],
+        [0, 4, 20],
+        [16, 4, 21],
+        [25, 25, 25],
+        [7, 19, 2],
+        [12, 8, 16],
+    ];
+
+    fn check<S: Stepping + Copy>(
+        key: &str,
+        reflector: Reflector,
+        stepping: S,
+        entry_wheel: EntryWheel,
+    ) {
+        let text = to_letters(&"THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG".repeat(30)).unwrap();
+        let key: EnigmaKey = key.parse().unwrap();
+
+        let mut tables = BatchTables::new(&key, reflector, stepping);
+        tables.set_entry_wheel(entry_wheel);
+        let mut batch = TableBatch::new(&tables, &POSITIONS);
+        let keys = batch.keys();
+        let mut outputs = Default::default();
+        batch.encrypt_into(&text, &mut outputs).unwrap();
+        let stepped = batch.rotors();
+
+        for (lane, (key, output)) in keys.iter().zip(&outputs).enumerate() {
+            let rotors = [key.left_rotor, key.middle_rotor, key.right_rotor];
+            let positions: Vec<_> = rotors.iter().map(|r| r.rotor_position()).collect();
+            assert_eq!(positions, POSITIONS[lane]);
+
+            let mut enigma = Enigma::with_stepping(*key, reflector, stepping);
+            enigma.set_entry_wheel(entry_wheel);
+            let mut expected = Vec::new();
+            enigma.encrypt_into(&text, &mut expected).unwrap();
+            assert_eq!(output, &expected, "lane {}", lane);
+            assert_eq!(&stepped[lane], enigma.machine().rotors());
+        }
+    }
+
+    #[test]
+    fn lanes_match_enigma() {
+        check(
+            "I II III / 1 1 1 / 1 1 1 / AF TV KO BL RW",
+            ReflectorId::B.into(),
+            Ratchet,
+            EntryWheel::IDENTITY,
+        );
+        check(
+            "I II III / 13 3 21 / 1 1 1",
+            ReflectorId::C.into(),
+            Ratchet,
+            EntryWheel::IDENTITY,
+        );
+        check(
+            "Beta I II III / 5 2 8 17 / 3 1 1 1 / AZ QM",
+            ReflectorId::BThin.into(),
+            Ratchet,
+            EntryWheel::IDENTITY,
+        );
+        // The reflector starts just before it turns over, so it moves part way through.
+        check(
+            "G312-I G312-II G312-III / 3 9 21 / 1 5 7",
+            Reflector::new(ReflectorId::G312_UKW, 25, 4),
+            Cog,
+            EntryWheel::QWERTZU,
+        );
+    }
+
+    #[test]
+    fn histograms() {
+        let text = to_letters("HELLOWORLD").unwrap();
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1".parse().unwrap();
+        let tables = BatchTables::new(&key, ReflectorId::B, Ratchet);
+
+        let mut outputs = Default::default();
+        TableBatch::new(&tables, &POSITIONS)
+            .encrypt_into(&text, &mut outputs)
+            .unwrap();
+        let histograms = TableBatch::new(&tables, &POSITIONS)
+            .histograms(&text)
+            .unwrap();
+
+        for (histogram, output) in histograms.iter().zip(&outputs) {
+            let mut expected = [0; 26];
+            output.iter().for_each(|&c| expected[c as usize] += 1);
+            assert_eq!(histogram, &expected);
+        }
+    }
+
+    #[test]
+    fn invalid_letter() {
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1".parse().unwrap();
+        let tables = BatchTables::new(&key, ReflectorId::B, Ratchet);
+        let mut batch = TableBatch::new(&tables, &[[0; 3]]);
+        let mut outputs = Default::default();
+
+        assert_eq!(
+            batch.encrypt_into(&[0, 26], &mut outputs),
+            Err(EnigmaError::InvalidLetter {
+                position: 1,
+                letter: 26
+            })
+        );
+        assert!(outputs[0].is_empty());
+        assert_eq!(batch.keys()[0].right_rotor.rotor_position(), 0);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::error::EnigmaError;
+
+use super::{
+    permutation::Permutation,
+    scrambler::ScramblerTables,
+    stepping::{Ratchet, Stepping},
+    svg::Diagram,
+    trace::{Component, Direction, Stage, Trace},
+    EntryWheel, Plugboard, Reflector, Rotor,
+};
+
+/// A machine with an arbitrary number of rotors, ordered from left to right. Which of them move is up
+/// to the stepping policy; the ratchet only drives the rightmost three, leaving the rest as stators.
+#[derive(Debug, Clone)]
+pub struct Machine<const N: usize, S = Ratchet> {
+    rotors: [Rotor; N],
+    reflector: Reflector,
+    entry_wheel: EntryWheel,
+    plugboard: Plugboard,
+    stepping: S,
+    // The plugboard and entry wheel combined, so the hot path only does one lookup each way.
+    input_wiring: [u8; 26],
+    output_wiring: [u8; 26],
+    // Where the rotors and reflector started, and how many keys have been pressed since, so `seek`
+    // can go backwards.
+    start: ([Rotor; N], Reflector),
+    offset: u64,
+}
+
+impl<const N: usize, S: Stepping> Machine<N, S> {
+    pub fn new(
+        rotors: [Rotor; N],
+        reflector: impl Into<Reflector>,
+        plugboard: Plugboard,
+        stepping: S,
+    ) -> Self {
+        let reflector = reflector.into();
+        let mut machine = Self {
+            rotors,
+            reflector,
+            entry_wheel: EntryWheel::IDENTITY,
+            plugboard,
+            stepping,
+            input_wiring: [0; 26],
+            output_wiring: [0; 26],
+            start: (rotors, reflector),
+            offset: 0,
+        };
+        machine.update_io_wiring();
+
+        machine
+    }
+
+    /// Set the machine's entry wheel. This defaults to the identity wheel of the military machines.
+    pub fn set_entry_wheel(&mut self, entry_wheel: EntryWheel) {
+        self.entry_wheel = entry_wheel;
+        self.update_io_wiring();
+    }
+
+    fn update_io_wiring(&mut self) {
+        for c in 0..26 {
+            self.input_wiring[c as usize] = self.entry_wheel.forward(self.plugboard.forward(c));
+            self.output_wiring[c as usize] = self.plugboard.backward(self.entry_wheel.backward(c));
+        }
+    }
+
+    /// Get a reference to the machine's rotors, ordered from left to right.
+    pub fn rotors(&self) -> &[Rotor; N] {
+        &self.rotors
+    }
+
+    /// Get a reference to the machine's reflector.
+    pub fn reflector(&self) -> &Reflector {
+        &self.reflector
+    }
+
+    /// Get a reference to the machine's entry wheel.
+    pub fn entry_wheel(&self) -> &EntryWheel {
+        &self.entry_wheel
+    }
+
+    /// Get a reference to the machine's plugboard.
+    pub fn plugboard(&self) -> &Plugboard {
+        &self.plugboard
+    }
+
+    /// Get the number of keys pressed since the machine was set up.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Moves the rotors to where they'd be after `offset` key presses from where the machine was set
+    /// up, going forwards or backwards. This doesn't replay the key presses, so it's quick even for
+    /// long messages.
+    pub fn seek(&mut self, offset: u64) {
+        let steps = match offset.checked_sub(self.offset) {
+            Some(steps) => steps,
+            None => {
+                self.rotors = self.start.0;
+                self.reflector = self.start.1;
+                offset
+            }
+        };
+
+        self.stepping
+            .advance(&mut self.rotors, &mut self.reflector, steps);
+        self.offset = offset;
+    }
+
+    /// Undoes the last key press. The double-step means some positions can be reached in more than
+    /// one way, so this seeks from where the machine was set up rather than turning the rotors back.
+    pub fn step_back(&mut self) {
+        assert!(
+            self.offset > 0,
+            "Can't step back past the starting position"
+        );
+        self.seek(self.offset - 1);
+    }
+
+    pub fn encrypt(&mut self, c: char) -> char {
+        assert!(c.is_ascii_uppercase(), "Invalid character {:?}", c);
+        let c = self.encrypt_letter(c as u8 - b'A');
+        (c + b'A') as char
+    }
+
+    /// Like `encrypt`, but returns an error instead of panicking if `c` isn't an uppercase ASCII
+    /// letter. A single letter has no position, so it's always reported at position 0.
+    pub fn try_encrypt(&mut self, c: char) -> Result<char, EnigmaError> {
+        if !c.is_ascii_uppercase() {
+            return Err(EnigmaError::InvalidCharacter {
+                position: 0,
+                character: c,
+            });
+        }
+
+        let c = self.encrypt_letter(c as u8 - b'A');
+        Ok((c + b'A') as char)
+    }
+
+    /// Encrypts a whole message, checking it's all uppercase ASCII letters before the machine is
+    /// stepped, so the machine is left untouched on error.
+    pub fn try_encrypt_str(&mut self, text: &str) -> Result<String, EnigmaError> {
+        if let Some((position, character)) = text
+            .chars()
+            .enumerate()
+            .find(|(_, c)| !c.is_ascii_uppercase())
+        {
+            return Err(EnigmaError::InvalidCharacter {
+                position,
+                character,
+            });
+        }
+
+        Ok(text
+            .bytes()
+            .map(|c| (self.encrypt_letter(c - b'A') + b'A') as char)
+            .collect())
+    }
+
+    /// Encrypts letters in the machine's internal alphabet, where A is 0 and Z is 25, overwriting
+    /// them. They're all checked before the machine is stepped, so the machine is left untouched on
+    /// error.
+    pub fn encrypt_in_place(&mut self, letters: &mut [u8]) -> Result<(), EnigmaError> {
+        check_letters(letters)?;
+
+        for c in letters {
+            *c = self.encrypt_letter(*c);
+        }
+
+        Ok(())
+    }
+
+    /// Like `encrypt_in_place`, but appends the encrypted letters to `out`.
+    pub fn encrypt_into(&mut self, letters: &[u8], out: &mut Vec<u8>) -> Result<(), EnigmaError> {
+        check_letters(letters)?;
+
+        out.reserve(letters.len());
+        out.extend(letters.iter().map(|&c| self.encrypt_letter(c)));
+
+        Ok(())
+    }
+
+    /// Like `try_encrypt`, but records the letter at every stage of the signal path. A single letter
+    /// has no position, so an invalid one is always reported at position 0.
+    pub fn encrypt_traced(&mut self, c: char) -> Result<Trace, EnigmaError> {
+        if !c.is_ascii_uppercase() {
+            return Err(EnigmaError::InvalidCharacter {
+                position: 0,
+                character: c,
+            });
+        }
+
+        let positions = |rotors: &[Rotor]| rotors.iter().map(|r| r.rotor_position).collect();
+        let positions_before = positions(&self.rotors);
+        self.stepping.step(&mut self.rotors, &mut self.reflector);
+        self.offset += 1;
+
+        let mut stages = Vec::with_capacity(2 * N + 7);
+        let mut record = |component, input, output, offset| {
+            stages.push(Stage::new(component, input, output, offset));
+            output
+        };
+
+        let input = c;
+        let mut c = c as u8 - b'A';
+        let forward = Direction::Forward;
+        c = record(
+            Component::Plugboard(forward),
+            c,
+            self.plugboard.forward(c),
+            None,
+        );
+        c = record(
+            Component::EntryWheel(forward),
+            c,
+            self.entry_wheel.forward(c),
+            None,
+        );
+        for (slot, rotor) in self.rotors.iter().enumerate().rev() {
+            let component = Component::Rotor {
+                slot,
+                id: rotor.id,
+                direction: forward,
+            };
+            c = record(component, c, rotor.forward(c), Some(rotor.offset()));
+        }
+
+        let greek_rotor = self.reflector.greek_rotor;
+        if let Some(greek_rotor) = &greek_rotor {
+            let component = Component::GreekRotor {
+                id: greek_rotor.id,
+                direction: forward,
+            };
+            c = record(
+                component,
+                c,
+                greek_rotor.forward(c),
+                Some(greek_rotor.offset()),
+            );
+        }
+        c = record(
+            Component::Reflector,
+            c,
+            self.reflector.reflect(c),
+            Some(self.reflector.offset()),
+        );
+
+        let backward = Direction::Backward;
+        if let Some(greek_rotor) = &greek_rotor {
+            let component = Component::GreekRotor {
+                id: greek_rotor.id,
+                direction: backward,
+            };
+            c = record(
+                component,
+                c,
+                greek_rotor.backward(c),
+                Some(greek_rotor.offset()),
+            );
+        }
+        for (slot, rotor) in self.rotors.iter().enumerate() {
+            let component = Component::Rotor {
+                slot,
+                id: rotor.id,
+                direction: backward,
+            };
+            c = record(component, c, rotor.backward(c), Some(rotor.offset()));
+        }
+        c = record(
+            Component::EntryWheel(backward),
+            c,
+            self.entry_wheel.backward(c),
+            None,
+        );
+        record(
+            Component::Plugboard(backward),
+            c,
+            self.plugboard.backward(c),
+            None,
+        );
+
+        Ok(Trace::new(
+            input,
+            positions_before,
+            positions(&self.rotors),
+            stages,
+        ))
+    }
+
+    /// Draws the machine's wiring at its current position as an SVG diagram, with each rotor's wiring
+    /// turned by its position and ring setting. If a trace from `encrypt_traced` is given, its signal
+    /// path is highlighted, so it should be the last key pressed.
+    pub fn to_svg(&self, trace: Option<&Trace>) -> String {
+        Diagram::new(self, trace).to_string()
+    }
+
+    /// Returns the permutation the whole machine applies at the rotors' current position, without
+    /// stepping them first.
+    pub fn permutation(&self) -> Permutation {
+        let mut mapping = [0; 26];
+        for (c, m) in (0..).zip(&mut mapping) {
+            *m = self.output_wiring[self.scramble(self.input_wiring[c as usize]) as usize];
+        }
+
+        Permutation::new(mapping)
+    }
+
+    /// Records the scrambler, everything between the plugboard sockets, for each of the next `len`
+    /// key presses. This machine isn't moved.
+    pub fn scrambler_tables(&self, len: usize) -> ScramblerTables
+    where
+        S: Clone,
+    {
+        let mut machine = self.clone();
+        let mut tables = Vec::with_capacity(len);
+        let mut paths = Vec::with_capacity(len);
+        for _ in 0..len {
+            machine
+                .stepping
+                .step(&mut machine.rotors, &mut machine.reflector);
+
+            let mut table = [0; 26];
+            let mut path = [0; 26];
+            for ((c, t), p) in (0..).zip(&mut table).zip(&mut path) {
+                let entered = machine.entry_wheel.forward(c);
+                *t = machine.entry_wheel.backward(machine.scramble(entered));
+
+                let rotated = machine
+                    .rotors
+                    .iter()
+                    .rev()
+                    .fold(entered, |c, r| r.forward(c));
+                *p = machine.reflector.contact(rotated);
+            }
+            tables.push(table);
+            paths.push(path);
+        }
+
+        ScramblerTables::new(tables, paths)
+    }
+
+    /// Requires that `c` is in the range 0..26.
+    fn encrypt_letter(&mut self, c: u8) -> u8 {
+        self.stepping.step(&mut self.rotors, &mut self.reflector);
+        self.offset += 1;
+
+        // Plugboard and entry wheel in, then the rotors, then entry wheel and plugboard out
+        let c = self.scramble(self.input_wiring[c as usize]);
+        self.output_wiring[c as usize]
+    }
+
+    /// Passes `c` through the rotors and reflector, without stepping. Requires that `c` is in the range
+    /// 0..26.
+    fn scramble(&self, mut c: u8) -> u8 {
+        // Right to left. N is a constant, so these loops get unrolled.
+        for rotor in self.rotors.iter().rev() {
+            c = rotor.forward(c);
+        }
+
+        // Reflector
+        c = self.reflector.forward(c);
+
+        // Left to right
+        for rotor in &self.rotors {
+            c = rotor.backward(c);
+        }
+
+        c
+    }
+}
+
+pub(super) fn check_letters(letters: &[u8]) -> Result<(), EnigmaError> {
+    match letters.iter().position(|&c| c >= 26) {
+        Some(position) => Err(EnigmaError::InvalidLetter {
+            position,
+            letter: letters[position],
+        }),
+        None => Ok(()),
+    }
+}
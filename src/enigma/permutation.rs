@@ -0,0 +1,411 @@
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+
+use crate::error::EnigmaError;
+
+use super::{decode_wiring, Plugboard, ReflectorId, Rotor};
+
+/// A rearrangement of the 26 letters, in the machine's internal alphabet. Every part of the machine
+/// is one, and so is the whole machine at a given rotor position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Permutation([u8; 26]);
+
+impl Permutation {
+    pub const IDENTITY: Self = {
+        let mut mapping = [0; 26];
+
+        let mut i = 0;
+        while i < 26 {
+            mapping[i] = i as u8;
+            i += 1;
+        }
+
+        Self(mapping)
+    };
+
+    /// Requires that `mapping` is a permutation of 0..26.
+    pub(super) fn new(mapping: [u8; 26]) -> Self {
+        Self(mapping)
+    }
+
+    /// Creates a permutation from a 26-letter string, where the Nth letter is where the Nth letter of
+    /// the alphabet goes.
+    pub fn from_wiring(wiring: &str) -> Result<Self, EnigmaError> {
+        decode_wiring(wiring).map(Self)
+    }
+
+    /// Get a reference to where each letter goes.
+    pub fn mapping(&self) -> &[u8; 26] {
+        &self.0
+    }
+
+    /// Requires that `c` is in the range 0..26.
+    pub fn apply(&self, c: u8) -> u8 {
+        self.0[c as usize]
+    }
+
+    /// Returns the permutation which applies this one and then `other`. This is the order Rejewski
+    /// wrote products in, so his AD is `a.then(&d)`.
+    pub fn then(&self, other: &Permutation) -> Self {
+        let mut mapping = [0; 26];
+        for (m, &c) in mapping.iter_mut().zip(&self.0) {
+            *m = other.apply(c);
+        }
+
+        Self(mapping)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let mut mapping = [0; 26];
+        for (i, &c) in (0..).zip(&self.0) {
+            mapping[c as usize] = i;
+        }
+
+        Self(mapping)
+    }
+
+    /// Returns this permutation conjugated by `by`, meaning `by` undone, then this, then `by`. Its
+    /// cycles are this permutation's cycles with every letter passed through `by`, which is why the
+    /// plugboard doesn't change the cycle type of the machine.
+    pub fn conjugate(&self, by: &Permutation) -> Self {
+        by.inverse().then(self).then(by)
+    }
+
+    /// Splits the permutation into cycles, including letters that are left alone as cycles of one.
+    /// Each cycle starts with its first letter in the alphabet, and they're ordered by that letter.
+    pub fn cycles(&self) -> Vec<Vec<u8>> {
+        let mut cycles = Vec::new();
+        let mut seen = [false; 26];
+
+        for start in 0..26 {
+            if seen[start as usize] {
+                continue;
+            }
+
+            let mut cycle = Vec::new();
+            let mut c = start;
+            while !seen[c as usize] {
+                seen[c as usize] = true;
+                cycle.push(c);
+                c = self.apply(c);
+            }
+            cycles.push(cycle);
+        }
+
+        cycles
+    }
+
+    /// Returns the lengths of the cycles. This is unchanged by conjugation, so it's a fingerprint
+    /// that the plugboard can't hide.
+    pub fn cycle_type(&self) -> CycleType {
+        // The catalogues call this for every rotor position, so count the lengths without collecting
+        // the cycles.
+        let mut lengths = Vec::new();
+        let mut seen = [false; 26];
+        for start in 0..26 {
+            let mut length = 0;
+            let mut c = start;
+            while !seen[c as usize] {
+                seen[c as usize] = true;
+                length += 1;
+                c = self.apply(c);
+            }
+            if length > 0 {
+                lengths.push(length);
+            }
+        }
+        lengths.sort_unstable_by(|a, b| b.cmp(a));
+
+        CycleType(lengths)
+    }
+
+    /// Returns the letters the permutation leaves alone.
+    pub fn fixed_points(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..26).filter(move |&c| self.apply(c) == c)
+    }
+
+    /// Whether applying the permutation twice leaves every letter where it started. An Enigma is
+    /// one at every position, which is why the same key decrypts and encrypts.
+    pub fn is_involution(&self) -> bool {
+        (0..26).all(|c| self.apply(self.apply(c)) == c)
+    }
+}
+
+impl Default for Permutation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl TryFrom<[u8; 26]> for Permutation {
+    type Error = EnigmaError;
+
+    fn try_from(mapping: [u8; 26]) -> Result<Self, Self::Error> {
+        let mut seen = [false; 26];
+        for (position, &letter) in mapping.iter().enumerate() {
+            if letter >= 26 {
+                return Err(EnigmaError::InvalidLetter { position, letter });
+            }
+            if seen[letter as usize] {
+                return Err(EnigmaError::DuplicateLetter {
+                    position,
+                    letter: (letter + b'A') as char,
+                });
+            }
+            seen[letter as usize] = true;
+        }
+
+        Ok(Self(mapping))
+    }
+}
+
+impl From<Rotor> for Permutation {
+    /// The rotor's wiring from right to left, at its current position and ring setting.
+    fn from(rotor: Rotor) -> Self {
+        let mut mapping = [0; 26];
+        for (c, m) in (0..).zip(&mut mapping) {
+            *m = rotor.forward(c);
+        }
+
+        Self(mapping)
+    }
+}
+
+impl From<ReflectorId> for Permutation {
+    fn from(id: ReflectorId) -> Self {
+        Self(*id.wiring())
+    }
+}
+
+impl From<Plugboard> for Permutation {
+    /// The plugboard's wiring on the way in. With the Uhr fitted, the way out is its inverse.
+    fn from(plugboard: Plugboard) -> Self {
+        Self(*plugboard.wiring())
+    }
+}
+
+impl Display for Permutation {
+    /// Prints the permutation in cycle notation, such as `(AFK)(BX)`, leaving out letters which are
+    /// left alone. The identity is `()`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut cycles = self.cycles();
+        cycles.retain(|cycle| cycle.len() > 1);
+        if cycles.is_empty() {
+            return f.write_str("()");
+        }
+
+        for cycle in cycles {
+            f.write_str("(")?;
+            for c in cycle {
+                write!(f, "{}", (c + b'A') as char)?;
+            }
+            f.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Permutation {
+    type Err = EnigmaError;
+
+    /// Parses cycle notation, such as `(AFK)(BX)`, with optional whitespace between the cycles.
+    /// Letters not in any cycle are left alone.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mapping = Self::IDENTITY.0;
+        let mut seen = [false; 26];
+        let mut cycle: Option<Vec<u8>> = None;
+
+        for (position, c) in s.chars().enumerate() {
+            match (c, &mut cycle) {
+                ('(', None) => cycle = Some(Vec::new()),
+                (')', Some(letters)) => {
+                    for (i, &letter) in letters.iter().enumerate() {
+                        mapping[letter as usize] = letters[(i + 1) % letters.len()];
+                    }
+                    cycle = None;
+                }
+                (c, Some(letters)) if c.is_ascii_uppercase() => {
+                    let letter = c as u8 - b'A';
+                    if seen[letter as usize] {
+                        return Err(EnigmaError::DuplicateLetter {
+                            position,
+                            letter: c,
+                        });
+                    }
+                    seen[letter as usize] = true;
+                    letters.push(letter);
+                }
+                (c, None) if c.is_whitespace() => {}
+                _ => {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position,
+                        character: c,
+                    })
+                }
+            }
+        }
+
+        if cycle.is_some() {
+            return Err(EnigmaError::InvalidCycles(s.to_owned()));
+        }
+
+        Ok(Self(mapping))
+    }
+}
+
+/// The lengths of a permutation's cycles, longest first, counting letters which are left alone as
+/// cycles of one. Two permutations have the same cycle type exactly when one is a conjugate of the
+/// other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CycleType(Vec<usize>);
+
+impl CycleType {
+    /// Get a reference to the cycle lengths, longest first. They always add up to 26.
+    pub fn lengths(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl Display for CycleType {
+    /// Prints the lengths separated by spaces, such as `13 13`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, length) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", length)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::RotorId;
+
+    fn p(cycles: &str) -> Permutation {
+        cycles.parse().unwrap()
+    }
+
+    #[test]
+    fn composition() {
+        // `then` applies itself first, so A goes to B and then on to C.
+        assert_eq!(p("(AB)").then(&p("(BC)")), p("(ACB)"));
+        assert_eq!(p("(BC)").then(&p("(AB)")), p("(ABC)"));
+        assert_eq!(p("(AFK)(BX)").then(&Permutation::IDENTITY), p("(AFK)(BX)"));
+
+        // Rotor I at position A with ring setting 1 is its plain wiring.
+        let rotor_i = Permutation::from(Rotor::new(RotorId::I, 0, 0));
+        assert_eq!(
+            rotor_i,
+            Permutation::from_wiring("EKMFLGDQVZNTOWYHXUSPAIBRCJ").unwrap()
+        );
+        let reflector_b = Permutation::from(ReflectorId::B);
+        for c in 0..26 {
+            assert_eq!(
+                rotor_i.then(&reflector_b).apply(c),
+                reflector_b.apply(rotor_i.apply(c))
+            );
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        let rotor_i = Permutation::from(Rotor::new(RotorId::I, 0, 0));
+        assert_eq!(
+            rotor_i.inverse(),
+            Permutation::from_wiring("UWYGADFPVZBECKMTHXSLRINQOJ").unwrap()
+        );
+        assert_eq!(rotor_i.then(&rotor_i.inverse()), Permutation::IDENTITY);
+        assert_eq!(rotor_i.inverse().then(&rotor_i), Permutation::IDENTITY);
+        assert_eq!(p("(AFK)(BX)").inverse(), p("(AKF)(BX)"));
+
+        // A reflector is its own inverse.
+        let reflector_b = Permutation::from(ReflectorId::B);
+        assert_eq!(reflector_b.inverse(), reflector_b);
+        assert!(reflector_b.is_involution());
+        assert!(!rotor_i.is_involution());
+    }
+
+    #[test]
+    fn cycles() {
+        let permutation = p("(KAF) (XB)");
+        assert_eq!(permutation.to_string(), "(AFK)(BX)");
+        assert_eq!(
+            &permutation.cycles()[..3],
+            &[vec![0, 5, 10], vec![1, 23], vec![2]]
+        );
+        assert_eq!(permutation.cycles().len(), 23);
+        assert_eq!(permutation.fixed_points().count(), 21);
+        assert_eq!(Permutation::IDENTITY.to_string(), "()");
+    }
+
+    #[test]
+    fn cycle_type() {
+        let cycle_type = p("(AFK)(BX)").cycle_type();
+        assert_eq!(&cycle_type.lengths()[..3], &[3, 2, 1]);
+        assert_eq!(cycle_type.lengths().iter().sum::<usize>(), 26);
+
+        assert_eq!(
+            Permutation::from(ReflectorId::B).cycle_type().to_string(),
+            "2 2 2 2 2 2 2 2 2 2 2 2 2"
+        );
+        assert_eq!(
+            p("(ABCDEFGHIJKLM)(NOPQRSTUVWXYZ)").cycle_type().to_string(),
+            "13 13"
+        );
+
+        // Conjugating relabels the letters in each cycle, but leaves the lengths alone.
+        let by = p("(AB)");
+        assert_eq!(p("(AFK)(BX)").conjugate(&by), p("(AX)(BFK)"));
+        let rotor_i = Permutation::from(Rotor::new(RotorId::I, 0, 0));
+        let plugboard = Permutation::from(Plugboard::new(&[('A', 'F'), ('T', 'V'), ('K', 'O')]));
+        assert_eq!(
+            rotor_i.conjugate(&plugboard).cycle_type(),
+            rotor_i.cycle_type()
+        );
+        assert_ne!(rotor_i.conjugate(&plugboard), rotor_i);
+    }
+
+    #[test]
+    fn invalid_permutations() {
+        assert_eq!(
+            "(ABA)".parse::<Permutation>(),
+            Err(EnigmaError::DuplicateLetter {
+                position: 3,
+                letter: 'A'
+            })
+        );
+        assert_eq!(
+            "(AB".parse::<Permutation>(),
+            Err(EnigmaError::InvalidCycles("(AB".to_owned()))
+        );
+        assert_eq!(
+            "(Ab)".parse::<Permutation>(),
+            Err(EnigmaError::InvalidCharacter {
+                position: 2,
+                character: 'b'
+            })
+        );
+
+        let mut mapping = *Permutation::IDENTITY.mapping();
+        mapping[4] = 3;
+        assert_eq!(
+            Permutation::try_from(mapping),
+            Err(EnigmaError::DuplicateLetter {
+                position: 4,
+                letter: 'D'
+            })
+        );
+        mapping[4] = 26;
+        assert_eq!(
+            Permutation::try_from(mapping),
+            Err(EnigmaError::InvalidLetter {
+                position: 4,
+                letter: 26
+            })
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use crate::error::EnigmaError;
+
+use super::{machine::check_letters, Plugboard, ReflectorKind};
+
+/// The scrambler, meaning the entry wheel, rotors and reflector, recorded as one permutation per key
+/// press. Only the plugboard is left out, so a rotor setting can be tried with a new plugboard at the
+/// cost of a few lookups per letter, rather than running the whole rotor stack again.
+/// Made with `Enigma::scrambler_tables`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramblerTables {
+    tables: Vec<[u8; 26]>,
+    // Where each letter meets the reflector's own wiring at each key press, and the way back, so the
+    // reflector can be rewired too.
+    paths: Vec<[u8; 26]>,
+    inverse_paths: Vec<[u8; 26]>,
+}
+
+impl ScramblerTables {
+    pub(super) fn new(tables: Vec<[u8; 26]>, paths: Vec<[u8; 26]>) -> Self {
+        let inverse_paths = paths
+            .iter()
+            .map(|path| {
+                let mut inverse = [0; 26];
+                for (c, &p) in (0..).zip(path) {
+                    inverse[p as usize] = c;
+                }
+                inverse
+            })
+            .collect();
+
+        Self {
+            tables,
+            paths,
+            inverse_paths,
+        }
+    }
+
+    /// Get the number of key presses recorded.
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Get a reference to the permutation for each key press, in the machine's internal alphabet.
+    pub fn tables(&self) -> &[[u8; 26]] {
+        &self.tables
+    }
+
+    fn check_len(&self, letters: &[u8]) -> Result<(), EnigmaError> {
+        if letters.len() > self.tables.len() {
+            return Err(EnigmaError::TooManyLetters {
+                recorded: self.tables.len(),
+                found: letters.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts letters in the machine's internal alphabet with this plugboard, appending them to
+    /// `out`. This gives the same result as an `Enigma` with the recorded rotor setting and this
+    /// plugboard. Fails if there are more letters than recorded key presses.
+    pub fn encrypt_into(
+        &self,
+        plugboard: &Plugboard,
+        letters: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), EnigmaError> {
+        self.check_len(letters)?;
+        check_letters(letters)?;
+
+        out.reserve(letters.len());
+        out.extend(
+            letters
+                .iter()
+                .zip(&self.tables)
+                .map(|(&c, table)| plugboard.backward(table[plugboard.forward(c) as usize])),
+        );
+
+        Ok(())
+    }
+
+    /// Like `encrypt_into`, but with the reflector's wiring replaced, such as to try another UKW-D
+    /// wiring. The rotors and the reflector's position are as recorded, so a rewiring costs a few
+    /// lookups per letter rather than a new machine.
+    pub fn encrypt_into_rewired(
+        &self,
+        plugboard: &Plugboard,
+        reflector: impl Into<ReflectorKind>,
+        letters: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), EnigmaError> {
+        self.check_len(letters)?;
+        check_letters(letters)?;
+
+        let reflector = reflector.into();
+        let wiring = reflector.wiring();
+        out.reserve(letters.len());
+        out.extend(
+            letters
+                .iter()
+                .zip(self.paths.iter().zip(&self.inverse_paths))
+                .map(|(&c, (path, inverse))| {
+                    let reflected = wiring[path[plugboard.forward(c) as usize] as usize];
+                    plugboard.backward(inverse[reflected as usize])
+                }),
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{
+        stepping::{Cog, Stepping},
+        ukwd::UkwD,
+        Enigma, EnigmaKey, EntryWheel, Reflector, ReflectorId,
+    };
+
+    const TEXT: &[u8] = &[
+        7, 4, 11, 11, 14, 22, 14, 17, 11, 3, 19, 7, 4, 16, 20, 8, 2, 10, 1, 17, 14, 22, 13, 5, 14,
+        23, 9, 20, 12, 15, 18,
+    ];
+
+    // Checks the tables give the same output as the machine with each plugboard. `machine` builds
+    // the machine with the plugboard fitted.
+    fn check<S: Stepping + Clone>(machine: impl Fn(Plugboard) -> Enigma<S>) {
+        let tables = machine(Plugboard::new(&[])).scrambler_tables(TEXT.len());
+
+        for plugboard in ["", "AT BL DF GJ", "Uhr 27: AT BL DF GJ HM NW OP QY RZ VX"] {
+            let plugboard: Plugboard = plugboard.parse().unwrap();
+            let mut expected = Vec::new();
+            machine(plugboard)
+                .encrypt_into(TEXT, &mut expected)
+                .unwrap();
+
+            let mut out = Vec::new();
+            tables.encrypt_into(&plugboard, TEXT, &mut out).unwrap();
+            assert_eq!(out, expected, "{}", plugboard);
+        }
+    }
+
+    #[test]
+    fn tables_match_enigma() {
+        let with_plugboard = |key: &str, plugboard| {
+            let mut key: EnigmaKey = key.parse().unwrap();
+            key.set_plugboard(plugboard);
+            key
+        };
+
+        check(|plugboard| {
+            let key = with_plugboard("II IV V / 25 26 24 / 2 7 19", plugboard);
+            Enigma::new(key, ReflectorId::B)
+        });
+        check(|plugboard| {
+            let key = with_plugboard("Beta II IV I / 22 10 14 1 / 1 1 1 22", plugboard);
+            Enigma::new(key, ReflectorId::BThin)
+        });
+        check(|plugboard| {
+            let key = with_plugboard("G312-I G312-II G312-III / 19 19 21 / 1 1 1", plugboard);
+            let reflector = Reflector::new(ReflectorId::G312_UKW, 3, 0);
+            let mut enigma = Enigma::with_stepping(key, reflector, Cog);
+            enigma.set_entry_wheel(EntryWheel::QWERTZU);
+            enigma
+        });
+    }
+
+    #[test]
+    fn too_many_letters() {
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1".parse().unwrap();
+        let tables = Enigma::new(key, ReflectorId::B).scrambler_tables(10);
+        let plugboard = Plugboard::new(&[]);
+        let mut out = Vec::new();
+
+        assert_eq!(
+            tables.encrypt_into(&plugboard, &TEXT[..11], &mut out),
+            Err(EnigmaError::TooManyLetters {
+                recorded: 10,
+                found: 11
+            })
+        );
+        assert_eq!(
+            tables.encrypt_into_rewired(&plugboard, ReflectorId::C, &TEXT[..11], &mut out),
+            Err(EnigmaError::TooManyLetters {
+                recorded: 10,
+                found: 11
+            })
+        );
+        assert_eq!(
+            tables.encrypt_into(&plugboard, &[0, 26], &mut out),
+            Err(EnigmaError::InvalidLetter {
+                position: 1,
+                letter: 26
+            })
+        );
+        assert!(out.is_empty());
+
+        // Fewer letters than were recorded is fine.
+        tables
+            .encrypt_into(&plugboard, &TEXT[..5], &mut out)
+            .unwrap();
+        assert_eq!(out.len(), 5);
+    }
+
+    #[test]
+    fn rewired_reflector_matches_enigma() {
+        let key: EnigmaKey = "II IV V / 1 25 23 / 2 7 19 / AT BL DF GJ".parse().unwrap();
+        let first: UkwD = "AC BD EF GH IK LM NO PQ RS TU VW XZ".parse().unwrap();
+        let second: UkwD = "AZ BX CW DV EU FT GS HR IQ KP LO MN".parse().unwrap();
+        let tables = Enigma::new(key, first).scrambler_tables(TEXT.len());
+
+        for &ukwd in &[first, second] {
+            let mut expected = Vec::new();
+            Enigma::new(key, ukwd)
+                .encrypt_into(TEXT, &mut expected)
+                .unwrap();
+
+            let mut rewired = Vec::new();
+            tables
+                .encrypt_into_rewired(key.plugboard(), ukwd, TEXT, &mut rewired)
+                .unwrap();
+            assert_eq!(rewired, expected);
+        }
+
+        // The M4's Greek wheel sits between the rotors and the reflector's wiring.
+        let key: EnigmaKey = "Beta II IV I / 22 10 14 1 / 1 1 1 22 / AT BL"
+            .parse()
+            .unwrap();
+        let tables = Enigma::new(key, ReflectorId::BThin).scrambler_tables(TEXT.len());
+        let mut expected = Vec::new();
+        Enigma::new(key, ReflectorId::CThin)
+            .encrypt_into(TEXT, &mut expected)
+            .unwrap();
+        let mut rewired = Vec::new();
+        tables
+            .encrypt_into_rewired(key.plugboard(), ReflectorId::CThin, TEXT, &mut rewired)
+            .unwrap();
+        assert_eq!(rewired, expected);
+    }
+}
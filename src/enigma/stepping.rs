@@ -0,0 +1,255 @@
+use super::{Reflector, Rotor};
+
+/// How the rotors are driven on each key press.
+pub trait Stepping {
+    /// Steps the machine before a key press is encyphered. The rotors are ordered from left to right,
+    /// so the last one is the fast rotor.
+    fn step(&self, rotors: &mut [Rotor], reflector: &mut Reflector);
+
+    /// Steps the machine as if `n` keys had been pressed. Policies should override this where they
+    /// can jump ahead quicker than stepping one key at a time.
+    fn advance(&self, rotors: &mut [Rotor], reflector: &mut Reflector, n: u64) {
+        for _ in 0..n {
+            self.step(rotors, reflector);
+        }
+    }
+
+    /// Returns true if the policy can ever step the reflector.
+    fn moves_reflector(&self) -> bool {
+        false
+    }
+}
+
+/// The pawl and ratchet mechanism of the military machines. The middle rotor's pawl also pushes on
+/// its own notch, so it steps twice in a row when it reaches its notch (the double-step).
+/// There are only three pawls, so only the three rightmost rotors are ever driven.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ratchet;
+
+impl Ratchet {
+    fn step_rotors(left: &mut Rotor, middle: &mut Rotor, right: &mut Rotor) {
+        // If middle rotor notch - double-stepping
+        if middle.is_at_notch() {
+            middle.turnover();
+            left.turnover();
+        }
+        // If left-rotor notch
+        else if right.is_at_notch() {
+            middle.turnover();
+        }
+
+        right.turnover();
+    }
+
+    /// Only the right rotor moves between its notches, so this jumps from one carry to the next,
+    /// stepping one key at a time around each carry and double-step.
+    fn skip(left: &mut Rotor, middle: &mut Rotor, right: &mut Rotor, mut n: u64) {
+        while n > 0 {
+            if !middle.is_at_notch() {
+                match right.steps_to_notch() {
+                    Some(steps) if steps < n => {
+                        right.advance(steps);
+                        n -= steps;
+                    }
+                    _ => {
+                        right.advance(n);
+                        return;
+                    }
+                }
+            }
+
+            Ratchet::step_rotors(left, middle, right);
+            n -= 1;
+        }
+    }
+}
+
+impl Stepping for Ratchet {
+    fn step(&self, rotors: &mut [Rotor], _: &mut Reflector) {
+        if let [.., left, middle, right] = rotors {
+            Ratchet::step_rotors(left, middle, right);
+        }
+    }
+
+    /// The right rotor comes back round every 26 key presses, and each time round it moves the
+    /// middle and left rotors in the same way. They can only be in 676 places, so they soon come
+    /// back to one they've been in before (every 650 turns with single-notch rotors, giving the
+    /// period of 26 × 25 × 26), and every whole loop after that is skipped. This takes at most
+    /// 676 turns however far the machine is moved.
+    fn advance(&self, rotors: &mut [Rotor], _: &mut Reflector, n: u64) {
+        if let [.., left, middle, right] = rotors {
+            let turns = n / 26;
+            // The turn on which the middle and left rotors were last at each pair of positions.
+            let mut seen = [None; 26 * 26];
+
+            for turn in 0..turns {
+                let index =
+                    usize::from(middle.rotor_position()) * 26 + usize::from(left.rotor_position());
+                if let Some(start) = seen[index].replace(turn) {
+                    let remaining = (turns - turn) % (turn - start);
+                    Ratchet::skip(left, middle, right, remaining * 26 + n % 26);
+                    return;
+                }
+
+                Ratchet::skip(left, middle, right, 26);
+            }
+
+            Ratchet::skip(left, middle, right, n % 26);
+        }
+    }
+}
+
+/// A pure odometer, where each rotor steps only when the one to its right carries past a notch.
+/// This isn't any historical machine, but is useful to compare against the ratchet's double-step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Odometer;
+
+impl Odometer {
+    /// Steps the rotors `n` times, returning how many times the carry made it all the way through the
+    /// left rotor.
+    fn advance_rotors(rotors: &mut [Rotor], n: u64) -> u64 {
+        rotors
+            .iter_mut()
+            .rev()
+            .fold(n, |steps, rotor| rotor.advance(steps))
+    }
+
+    /// Returns true if the carry made it all the way through the left rotor.
+    fn step_rotors(rotors: &mut [Rotor]) -> bool {
+        for rotor in rotors.iter_mut().rev() {
+            let carry = rotor.is_at_notch();
+            rotor.turnover();
+
+            if !carry {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Stepping for Odometer {
+    fn step(&self, rotors: &mut [Rotor], _: &mut Reflector) {
+        Odometer::step_rotors(rotors);
+    }
+
+    fn advance(&self, rotors: &mut [Rotor], _: &mut Reflector, n: u64) {
+        Odometer::advance_rotors(rotors, n);
+    }
+}
+
+/// The Enigma G's cog wheels. These carry like an odometer so there's no double-step, and the left
+/// rotor carries into the reflector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cog;
+
+impl Stepping for Cog {
+    fn step(&self, rotors: &mut [Rotor], reflector: &mut Reflector) {
+        if Odometer::step_rotors(rotors) {
+            reflector.turnover();
+        }
+    }
+
+    fn advance(&self, rotors: &mut [Rotor], reflector: &mut Reflector, n: u64) {
+        let carries = Odometer::advance_rotors(rotors, n);
+        reflector.advance(carries);
+    }
+
+    fn moves_reflector(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{ReflectorId, RotorId};
+
+    fn rotors(start: &[u8; 3]) -> [Rotor; 3] {
+        [
+            Rotor::new(RotorId::I, start[0] - b'A', 0),
+            Rotor::new(RotorId::II, start[1] - b'A', 0),
+            Rotor::new(RotorId::III, start[2] - b'A', 0),
+        ]
+    }
+
+    fn positions(rotors: &[Rotor], reflector: &Reflector) -> String {
+        rotors
+            .iter()
+            .map(|rotor| rotor.rotor_position())
+            .chain(Some(reflector.position()))
+            .map(|p| (b'A' + p) as char)
+            .collect()
+    }
+
+    fn sequence(stepping: impl Stepping, start: &[u8; 3], presses: usize) -> Vec<String> {
+        let mut rotors = rotors(start);
+        let mut reflector = Reflector::new(ReflectorId::B, 0, 0);
+        (0..presses)
+            .map(|_| {
+                stepping.step(&mut rotors, &mut reflector);
+                positions(&rotors, &reflector)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ratchet_double_steps() {
+        // The middle rotor steps onto its notch (E), then steps again on the next key press along
+        // with the left rotor.
+        assert_eq!(
+            sequence(Ratchet, b"ADU", 4),
+            ["ADVA", "AEWA", "BFXA", "BFYA"]
+        );
+        // Only the right rotor's carry drives the middle rotor otherwise.
+        assert_eq!(sequence(Ratchet, b"QAY", 3), ["QAZA", "QAAA", "QABA"]);
+        assert_eq!(sequence(Ratchet, b"QAU", 2), ["QAVA", "QBWA"]);
+    }
+
+    #[test]
+    fn odometer_has_no_double_step() {
+        assert_eq!(
+            sequence(Odometer, b"ADU", 4),
+            ["ADVA", "AEWA", "AEXA", "AEYA"]
+        );
+        // The middle rotor only carries when the right rotor carries past its notch.
+        assert_eq!(sequence(Odometer, b"PEU", 2), ["PEVA", "QFWA"]);
+        assert_eq!(sequence(Odometer, b"QEU", 2), ["QEVA", "RFWA"]);
+    }
+
+    #[test]
+    fn cog_carries_through_the_reflector() {
+        assert_eq!(sequence(Cog, b"ADU", 3), ["ADVA", "AEWA", "AEXA"]);
+        // Every rotor is at its notch, so the carry reaches the reflector.
+        assert_eq!(sequence(Cog, b"QEU", 2), ["QEVA", "RFWB"]);
+    }
+
+    #[test]
+    fn advance_matches_stepping() {
+        fn check(stepping: impl Stepping + Copy, start: &[u8; 3]) {
+            for &n in &[0, 1, 25, 26, 27, 650, 16_900, 17_577, 100_003] {
+                let (mut stepped, mut advanced) = (rotors(start), rotors(start));
+                let mut stepped_reflector = Reflector::new(ReflectorId::B, 0, 0);
+                let mut advanced_reflector = stepped_reflector;
+                for _ in 0..n {
+                    stepping.step(&mut stepped, &mut stepped_reflector);
+                }
+                stepping.advance(&mut advanced, &mut advanced_reflector, n);
+                assert_eq!(
+                    positions(&advanced, &advanced_reflector),
+                    positions(&stepped, &stepped_reflector),
+                    "{} key presses from {:?}",
+                    n,
+                    std::str::from_utf8(start).unwrap()
+                );
+            }
+        }
+
+        for start in &[b"AAA", b"ADU", b"QEV", b"ZZZ"] {
+            check(Ratchet, start);
+            check(Odometer, start);
+            check(Cog, start);
+        }
+    }
+}
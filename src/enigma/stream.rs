@@ -0,0 +1,427 @@
+use std::io::{self, Read, Write};
+
+use super::{
+    stepping::{Ratchet, Stepping},
+    Enigma,
+};
+use crate::error::EnigmaError;
+
+const CHUNK_SIZE: usize = 8192;
+
+/// What the stream adapters do with bytes that aren't ASCII letters, such as spaces, digits,
+/// newlines and the bytes of non-ASCII characters like Ü.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonLetters {
+    /// Copy them to the output unchanged. They don't step the machine. Non-ASCII characters are
+    /// copied whole, so valid UTF-8 stays valid.
+    #[default]
+    Keep,
+    /// Leave them out of the output.
+    Drop,
+    /// Fail with an `io::ErrorKind::InvalidData` error wrapping `EnigmaError::InvalidByte`. The
+    /// machine isn't stepped for any of the chunk the byte was in.
+    Reject,
+}
+
+/// The state shared by the reader and the writer. The machine and the group count carry over from
+/// one chunk to the next, so the output doesn't depend on how the input was split up.
+struct Transcoder<S> {
+    enigma: Enigma<S>,
+    non_letters: NonLetters,
+    group_size: Option<usize>,
+    // The number of letters written so far, to know where the next group starts.
+    letters: usize,
+    // The number of bytes taken in so far, to report where a rejected byte was.
+    position: usize,
+    // Each chunk's letters in the internal alphabet, before and after encryption.
+    plain: Vec<u8>,
+    encrypted: Vec<u8>,
+}
+
+impl<S: Stepping> Transcoder<S> {
+    fn new(enigma: Enigma<S>) -> Self {
+        Self {
+            enigma,
+            non_letters: NonLetters::default(),
+            group_size: None,
+            letters: 0,
+            position: 0,
+            plain: Vec::with_capacity(CHUNK_SIZE),
+            encrypted: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn set_group_size(&mut self, group_size: Option<usize>) -> Result<(), EnigmaError> {
+        if group_size == Some(0) {
+            return Err(EnigmaError::InvalidGroupSize(0));
+        }
+
+        self.group_size = group_size;
+        Ok(())
+    }
+
+    /// Encrypts the chunk's letters in one go, then puts them back among the other bytes.
+    fn transcode(&mut self, input: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        if self.non_letters == NonLetters::Reject {
+            if let Some(i) = input.iter().position(|b| !b.is_ascii_alphabetic()) {
+                let e = EnigmaError::InvalidByte {
+                    position: self.position + i,
+                    byte: input[i],
+                };
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+        self.position += input.len();
+
+        self.plain.clear();
+        self.plain.extend(
+            input
+                .iter()
+                .filter(|b| b.is_ascii_alphabetic())
+                .map(|b| b.to_ascii_uppercase() - b'A'),
+        );
+        self.encrypted.clear();
+        // Every letter was just put in the range 0..26, so this can't fail.
+        self.enigma
+            .encrypt_into(&self.plain, &mut self.encrypted)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let mut encrypted = self.encrypted.iter();
+        for &b in input {
+            if !b.is_ascii_alphabetic() {
+                // Rejected bytes were caught above.
+                if self.non_letters == NonLetters::Keep {
+                    out.push(b);
+                }
+                continue;
+            }
+
+            if let Some(size) = self.group_size {
+                if self.letters > 0 && self.letters % size == 0 {
+                    out.push(b' ');
+                }
+            }
+
+            // There's one encrypted letter for every letter in the input.
+            out.extend(encrypted.next().map(|c| c + b'A'));
+            self.letters += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Encrypts everything written to it with an `Enigma`, and writes the result to the inner writer.
+///
+/// Letters are encrypted as uppercase, whatever case they were written in. Other bytes are kept or
+/// dropped according to `set_non_letters`, and the output can be split into groups with
+/// `set_group_size`.
+pub struct EnigmaWriter<W, S = Ratchet> {
+    inner: W,
+    transcoder: Transcoder<S>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write, S: Stepping> EnigmaWriter<W, S> {
+    pub fn new(enigma: Enigma<S>, inner: W) -> Self {
+        Self {
+            inner,
+            transcoder: Transcoder::new(enigma),
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    /// Set what happens to bytes that aren't ASCII letters. They're kept by default.
+    pub fn set_non_letters(&mut self, non_letters: NonLetters) {
+        self.transcoder.non_letters = non_letters;
+    }
+
+    /// Set the number of letters in each group of the output, or `None` to leave it ungrouped,
+    /// which is the default. Groups are separated by a space, and can't be empty.
+    pub fn set_group_size(&mut self, group_size: Option<usize>) -> Result<(), EnigmaError> {
+        self.transcoder.set_group_size(group_size)
+    }
+
+    /// Get a reference to the machine, which is left wherever the last letter stepped it to.
+    pub fn enigma(&self) -> &Enigma<S> {
+        &self.transcoder.enigma
+    }
+
+    /// Get a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the machine and the inner writer, so the machine can carry on elsewhere.
+    pub fn into_parts(self) -> (Enigma<S>, W) {
+        (self.transcoder.enigma, self.inner)
+    }
+}
+
+impl<W: Write, S: Stepping> Write for EnigmaWriter<W, S> {
+    /// Encrypts the whole of `buf` and writes it out before returning. If the inner writer fails,
+    /// the machine has already been stepped past `buf`, so the stream can't be resumed.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.clear();
+        self.transcoder.transcode(buf, &mut self.buf)?;
+        self.inner.write_all(&self.buf)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads from the inner reader in chunks, and encrypts them with an `Enigma`.
+///
+/// Letters are encrypted as uppercase, whatever case they were read in. Other bytes are kept or
+/// dropped according to `set_non_letters`, and the output can be split into groups with
+/// `set_group_size`.
+pub struct EnigmaReader<R, S = Ratchet> {
+    inner: R,
+    transcoder: Transcoder<S>,
+    // Encrypted output that hasn't been read yet, starting at `pos`.
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read, S: Stepping> EnigmaReader<R, S> {
+    pub fn new(enigma: Enigma<S>, inner: R) -> Self {
+        Self {
+            inner,
+            transcoder: Transcoder::new(enigma),
+            pending: Vec::with_capacity(CHUNK_SIZE),
+            pos: 0,
+        }
+    }
+
+    /// Set what happens to bytes that aren't ASCII letters. They're kept by default.
+    pub fn set_non_letters(&mut self, non_letters: NonLetters) {
+        self.transcoder.non_letters = non_letters;
+    }
+
+    /// Set the number of letters in each group of the output, or `None` to leave it ungrouped,
+    /// which is the default. Groups are separated by a space, and can't be empty.
+    pub fn set_group_size(&mut self, group_size: Option<usize>) -> Result<(), EnigmaError> {
+        self.transcoder.set_group_size(group_size)
+    }
+
+    /// Get a reference to the machine. It may have run ahead of what's been read, since the inner
+    /// reader is encrypted a chunk at a time.
+    pub fn enigma(&self) -> &Enigma<S> {
+        &self.transcoder.enigma
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the machine and the inner reader. Any output that hasn't been read yet is lost.
+    pub fn into_parts(self) -> (Enigma<S>, R) {
+        (self.transcoder.enigma, self.inner)
+    }
+}
+
+impl<R: Read, S: Stepping> Read for EnigmaReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // A chunk can come out empty if all of it was dropped, so keep going until there's
+        // something to return or the inner reader is done.
+        while self.pos == self.pending.len() {
+            let mut chunk = [0; CHUNK_SIZE];
+            let len = self.inner.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(0);
+            }
+
+            self.pending.clear();
+            self.pos = 0;
+            self.transcoder
+                .transcode(&chunk[..len], &mut self.pending)?;
+        }
+
+        let len = buf.len().min(self.pending.len() - self.pos);
+        buf[..len].copy_from_slice(&self.pending[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{EnigmaKey, ReflectorId};
+
+    fn enigma() -> Enigma {
+        let key: EnigmaKey = "II V III / 8 5 20 / 13 3 21 / AF TV KO BL RW"
+            .parse()
+            .unwrap();
+        Enigma::new(key, ReflectorId::B)
+    }
+
+    // Gives the adapters at most a few bytes at a time, so chunks split words and characters.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.1).min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn read_all(mut reader: impl Read, buf_size: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut buf = vec![0; buf_size];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(out),
+                len => out.extend_from_slice(&buf[..len]),
+            }
+        }
+    }
+
+    // Longer than a chunk, so the reader has to carry the machine and groups across chunks.
+    fn long_text() -> String {
+        "Das Oberkommando der Wehrmacht gibt bekannt: ".repeat(CHUNK_SIZE / 20)
+    }
+
+    #[test]
+    fn writer_across_writes() {
+        let text = long_text();
+        let letters: String = text
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let expected = enigma().try_encrypt_str(&letters).unwrap();
+
+        for &piece in &[1, 7, CHUNK_SIZE - 1, text.len()] {
+            let mut writer = EnigmaWriter::new(enigma(), Vec::new());
+            writer.set_non_letters(NonLetters::Drop);
+            for chunk in text.as_bytes().chunks(piece) {
+                writer.write_all(chunk).unwrap();
+            }
+
+            let (machine, out) = writer.into_parts();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+            assert_eq!(machine.offset(), letters.len() as u64);
+        }
+    }
+
+    #[test]
+    fn reader_across_chunks() {
+        let text = long_text();
+        let mut whole = EnigmaReader::new(enigma(), text.as_bytes());
+        whole.set_group_size(Some(5)).unwrap();
+        let expected = read_all(whole, text.len()).unwrap();
+
+        let letters: String = text
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let encrypted: String = expected
+            .iter()
+            .filter(|b| b.is_ascii_alphabetic())
+            .map(|&b| char::from(b))
+            .collect();
+        assert_eq!(encrypted, enigma().try_encrypt_str(&letters).unwrap());
+
+        for &(trickle, buf_size) in &[(3, 2), (CHUNK_SIZE, 1000), (5000, CHUNK_SIZE)] {
+            let mut reader = EnigmaReader::new(enigma(), Trickle(text.as_bytes(), trickle));
+            reader.set_group_size(Some(5)).unwrap();
+            assert_eq!(read_all(reader, buf_size).unwrap(), expected);
+        }
+
+        // The writer gives the same output as the reader.
+        let mut writer = EnigmaWriter::new(enigma(), Vec::new());
+        writer.set_group_size(Some(5)).unwrap();
+        for chunk in text.as_bytes().chunks(1234) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.get_ref(), &expected);
+    }
+
+    #[test]
+    fn groups() {
+        let mut reader = EnigmaReader::new(enigma(), &b"abcdefghijkl"[..]);
+        reader.set_group_size(Some(5)).unwrap();
+        let out = String::from_utf8(read_all(reader, 3).unwrap()).unwrap();
+
+        let expected = enigma().try_encrypt_str("ABCDEFGHIJKL").unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "{} {} {}",
+                &expected[..5],
+                &expected[5..10],
+                &expected[10..]
+            )
+        );
+
+        let mut writer = EnigmaWriter::new(enigma(), Vec::new());
+        assert_eq!(
+            writer.set_group_size(Some(0)),
+            Err(EnigmaError::InvalidGroupSize(0))
+        );
+    }
+
+    #[test]
+    fn non_letters() {
+        let text = "Grüße, Welt!";
+        let letters = enigma().try_encrypt_str("GREWELT").unwrap();
+        let read = |non_letters, trickle| {
+            let mut reader = EnigmaReader::new(enigma(), Trickle(text.as_bytes(), trickle));
+            reader.set_non_letters(non_letters);
+            read_all(reader, 4)
+        };
+
+        // Ü and ß are two bytes each, and can be split across chunks without harm.
+        for trickle in 1..4 {
+            let kept = String::from_utf8(read(NonLetters::Keep, trickle).unwrap()).unwrap();
+            assert_eq!(
+                kept,
+                format!("{}üß{}, {}!", &letters[..2], &letters[2..3], &letters[3..])
+            );
+
+            let dropped = read(NonLetters::Drop, trickle).unwrap();
+            assert_eq!(String::from_utf8(dropped).unwrap(), letters);
+
+            let error = read(NonLetters::Reject, trickle).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                error.into_inner().unwrap().downcast_ref::<EnigmaError>(),
+                Some(&EnigmaError::InvalidByte {
+                    position: 2,
+                    byte: 0xc3
+                })
+            );
+        }
+
+        // Letters are still accepted when rejecting everything else, in either case.
+        let mut writer = EnigmaWriter::new(enigma(), Vec::new());
+        writer.set_non_letters(NonLetters::Reject);
+        writer.write_all(b"Gre").unwrap();
+        writer.write_all(b"welt").unwrap();
+        assert_eq!(writer.get_ref(), letters.as_bytes());
+
+        let error = writer.write_all(b"AB CD").unwrap_err();
+        assert_eq!(
+            error.into_inner().unwrap().downcast_ref::<EnigmaError>(),
+            Some(&EnigmaError::InvalidByte {
+                position: 9,
+                byte: b' '
+            })
+        );
+        assert_eq!(writer.enigma().offset(), 7);
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    array,
+    fmt::{self, Display},
+};
+
+use super::{
+    machine::Machine,
+    stepping::Stepping,
+    trace::{Component, Direction, Trace},
+    ReflectorKind, Rotor,
+};
+
+// Everything is laid out on a grid of contacts, with the reflector on the left and the keyboard on
+// the right, so the signal goes in from the right and comes back out to it.
+const MARGIN: usize = 16;
+const TOP: usize = 56;
+const ROW: usize = 16;
+const WIDTH: usize = 96;
+const GAP: usize = 24;
+const KEYS: usize = 32;
+const FOOTER: usize = 24;
+
+const WIRE: &str = "#c0c0c0";
+const FORWARD: &str = "#d62728";
+const BACKWARD: &str = "#1f77b4";
+const PATH: f32 = 2.5;
+
+enum Wiring {
+    /// Where each contact on the right is wired to on the left.
+    Straight([u8; 26]),
+    /// Which contact on the right each one is paired with.
+    Reflector([u8; 26]),
+    /// Where each contact on the left is wired to on the right, for parts only crossed on the way back.
+    Backward([u8; 26]),
+}
+
+struct Column {
+    label: String,
+    setting: Option<String>,
+    wiring: Wiring,
+}
+
+impl Column {
+    fn rotor(rotor: &Rotor) -> Self {
+        Self {
+            label: rotor.id().name().to_owned(),
+            setting: Some(setting(rotor.rotor_position(), rotor.ring_setting())),
+            wiring: Wiring::Straight(array::from_fn(|c| rotor.forward(c as u8))),
+        }
+    }
+}
+
+/// A wiring diagram of a machine at its current position, made by `Enigma::to_svg`.
+pub(super) struct Diagram<'a> {
+    columns: Vec<Column>,
+    greek_rotor: bool,
+    rotors: usize,
+    uhr: bool,
+    trace: Option<&'a Trace>,
+}
+
+impl<'a> Diagram<'a> {
+    pub(super) fn new<const N: usize, S: Stepping>(
+        machine: &Machine<N, S>,
+        trace: Option<&'a Trace>,
+    ) -> Self {
+        let reflector = machine.reflector();
+        let label = match reflector.kind() {
+            ReflectorKind::Fixed(id) => id.name().to_owned(),
+            ReflectorKind::UkwD(_) => "UKW-D".to_owned(),
+        };
+        let mut columns = vec![Column {
+            label,
+            setting: Some(setting(reflector.position(), reflector.ring_setting())),
+            wiring: Wiring::Reflector(array::from_fn(|c| reflector.reflect(c as u8))),
+        }];
+
+        columns.extend(reflector.greek_rotor().map(Column::rotor));
+        columns.extend(machine.rotors().iter().map(Column::rotor));
+
+        let entry_wheel = machine.entry_wheel();
+        columns.push(Column {
+            label: "Entry wheel".to_owned(),
+            setting: None,
+            wiring: Wiring::Straight(array::from_fn(|c| entry_wheel.forward(c as u8))),
+        });
+
+        // The Uhr isn't reciprocal, so the way back gets its own column next to the way in, and each
+        // direction goes straight across the other's.
+        let plugboard = machine.plugboard();
+        let forward = Wiring::Straight(array::from_fn(|c| plugboard.forward(c as u8)));
+        match plugboard.uhr() {
+            Some(uhr) => {
+                let setting = format!("dial {:02}", uhr.dial());
+                columns.push(Column {
+                    label: "Uhr out".to_owned(),
+                    setting: Some(setting.clone()),
+                    wiring: Wiring::Backward(array::from_fn(|c| plugboard.backward(c as u8))),
+                });
+                columns.push(Column {
+                    label: "Uhr in".to_owned(),
+                    setting: Some(setting),
+                    wiring: forward,
+                });
+            }
+            None => columns.push(Column {
+                label: "Plugboard".to_owned(),
+                setting: None,
+                wiring: forward,
+            }),
+        }
+
+        Self {
+            columns,
+            greek_rotor: reflector.greek_rotor().is_some(),
+            rotors: N,
+            uhr: plugboard.uhr().is_some(),
+            trace,
+        }
+    }
+
+    fn column(&self, component: Component) -> usize {
+        let first_rotor = 1 + self.greek_rotor as usize;
+        match component {
+            Component::Reflector => 0,
+            Component::GreekRotor { .. } => 1,
+            Component::Rotor { slot, .. } => first_rotor + slot,
+            Component::EntryWheel(_) => first_rotor + self.rotors,
+            Component::Plugboard(Direction::Backward) if self.uhr => first_rotor + self.rotors + 1,
+            Component::Plugboard(_) => self.columns.len() - 1,
+        }
+    }
+
+    fn keys_x(&self) -> usize {
+        left(self.columns.len()) + KEYS / 2
+    }
+
+    fn write_path(&self, f: &mut fmt::Formatter<'_>, trace: &Trace) -> fmt::Result {
+        let stages = trace.stages();
+        let letter = |c: char| c as usize - 'A' as usize;
+
+        // From the key to the plugboard, and back out to the lamp.
+        for &(c, direction, colour) in &[
+            (trace.input(), Direction::Forward, FORWARD),
+            (trace.output(), Direction::Backward, BACKWARD),
+        ] {
+            let plugboard = right(self.column(Component::Plugboard(direction)));
+            let row = y(letter(c));
+            line(f, plugboard, row, self.keys_x() - 8, row, colour, PATH)?;
+        }
+
+        for stage in stages {
+            let column = self.column(stage.component());
+            let (input, output) = (letter(stage.input()), letter(stage.output()));
+            let (colour, from, to) = match direction(stage.component()) {
+                Some(Direction::Forward) => (FORWARD, input, output),
+                Some(Direction::Backward) => (BACKWARD, output, input),
+                None => {
+                    arc(f, right(column), input, output, FORWARD, PATH)?;
+                    continue;
+                }
+            };
+            line(f, right(column), y(from), left(column), y(to), colour, PATH)?;
+        }
+
+        // The gaps between the columns, crossed at the contact each stage left on.
+        for pair in stages.windows(2) {
+            let (a, b) = (
+                self.column(pair[0].component()),
+                self.column(pair[1].component()),
+            );
+            let colour = match direction(pair[1].component()) {
+                Some(Direction::Backward) => BACKWARD,
+                _ => FORWARD,
+            };
+            let row = y(letter(pair[0].output()));
+            line(f, right(a.min(b)), row, left(a.max(b)), row, colour, PATH)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Diagram<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.keys_x() + KEYS / 2 + MARGIN;
+        let height = y(26) + MARGIN + FOOTER;
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace" font-size="11">"#,
+            w = width,
+            h = height
+        )?;
+        writeln!(
+            f,
+            r#"<rect width="{}" height="{}" fill="white"/>"#,
+            width, height
+        )?;
+
+        for (i, column) in self.columns.iter().enumerate() {
+            let centre = left(i) + WIDTH / 2;
+            writeln!(
+                f,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#f7f7f7" stroke="#808080"/>"##,
+                left(i),
+                TOP,
+                WIDTH,
+                26 * ROW
+            )?;
+            writeln!(
+                f,
+                r#"<text x="{}" y="{}" text-anchor="middle" font-weight="bold">{}</text>"#,
+                centre,
+                TOP - 22,
+                Escaped(&column.label)
+            )?;
+            if let Some(setting) = &column.setting {
+                writeln!(
+                    f,
+                    r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                    centre,
+                    TOP - 8,
+                    setting
+                )?;
+            }
+
+            match &column.wiring {
+                Wiring::Straight(wiring) => {
+                    for (c, &w) in wiring.iter().enumerate() {
+                        line(f, right(i), y(c), left(i), y(w as usize), WIRE, 1.0)?;
+                    }
+                }
+                Wiring::Reflector(wiring) => {
+                    for (c, &w) in wiring.iter().enumerate() {
+                        if c < w as usize {
+                            arc(f, right(i), c, w as usize, WIRE, 1.0)?;
+                        }
+                    }
+                }
+                Wiring::Backward(wiring) => {
+                    for (c, &w) in wiring.iter().enumerate() {
+                        line(f, left(i), y(c), right(i), y(w as usize), WIRE, 1.0)?;
+                    }
+                }
+            }
+
+            // The contacts line up from one column to the next.
+            if i + 1 < self.columns.len() {
+                for c in 0..26 {
+                    line(f, right(i), y(c), left(i + 1), y(c), WIRE, 1.0)?;
+                }
+            }
+        }
+
+        for c in 0..26 {
+            writeln!(
+                f,
+                r#"<text x="{}" y="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                self.keys_x(),
+                y(c),
+                (c as u8 + b'A') as char
+            )?;
+        }
+
+        if let Some(trace) = self.trace {
+            self.write_path(f, trace)?;
+
+            let letter = |c: char| c as usize - 'A' as usize;
+            for &(c, colour) in &[(trace.input(), FORWARD), (trace.output(), BACKWARD)] {
+                writeln!(
+                    f,
+                    r#"<circle cx="{}" cy="{}" r="7" fill="none" stroke="{}" stroke-width="1.5"/>"#,
+                    self.keys_x(),
+                    y(letter(c)),
+                    colour
+                )?;
+            }
+
+            let positions = trace
+                .positions_after()
+                .iter()
+                .map(|&p| (p + b'A') as char)
+                .collect::<String>();
+            writeln!(
+                f,
+                r#"<text x="{}" y="{}">Key {} lights lamp {}, rotors at {}</text>"#,
+                MARGIN,
+                y(26) + FOOTER / 2,
+                trace.input(),
+                trace.output(),
+                positions
+            )?;
+        }
+
+        writeln!(f, "</svg>")
+    }
+}
+
+/// Escapes text for use in the SVG, since custom rotor names can be anything.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                _ => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which way the signal goes through a component, or `None` for the reflector, where it turns round.
+fn direction(component: Component) -> Option<Direction> {
+    match component {
+        Component::Plugboard(direction)
+        | Component::EntryWheel(direction)
+        | Component::Rotor { direction, .. }
+        | Component::GreekRotor { direction, .. } => Some(direction),
+        Component::Reflector => None,
+    }
+}
+
+fn setting(position: u8, ring_setting: u8) -> String {
+    format!(
+        "pos {} ring {}",
+        (position + b'A') as char,
+        (ring_setting + b'A') as char
+    )
+}
+
+fn left(column: usize) -> usize {
+    MARGIN + column * (WIDTH + GAP)
+}
+
+fn right(column: usize) -> usize {
+    left(column) + WIDTH
+}
+
+/// The height of the middle of a contact.
+fn y(c: usize) -> usize {
+    TOP + c * ROW + ROW / 2
+}
+
+fn line(
+    f: &mut fmt::Formatter<'_>,
+    x1: usize,
+    y1: usize,
+    x2: usize,
+    y2: usize,
+    colour: &str,
+    width: f32,
+) -> fmt::Result {
+    writeln!(
+        f,
+        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+        x1, y1, x2, y2, colour, width
+    )
+}
+
+/// A loop out of the right side of the reflector joining two contacts, bulging further the further
+/// apart they are.
+fn arc(
+    f: &mut fmt::Formatter<'_>,
+    x: usize,
+    a: usize,
+    b: usize,
+    colour: &str,
+    width: f32,
+) -> fmt::Result {
+    let depth = 8 + (WIDTH - 16) * (a as isize - b as isize).unsigned_abs() / 25;
+    writeln!(
+        f,
+        r#"<path d="M {x} {ya} C {c} {ya} {c} {yb} {x} {yb}" fill="none" stroke="{colour}" stroke-width="{width}"/>"#,
+        x = x,
+        c = x - depth,
+        ya = y(a),
+        yb = y(b),
+        colour = colour,
+        width = width
+    )
+}
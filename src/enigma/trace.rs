@@ -0,0 +1,177 @@
+use std::fmt::Display;
+
+use super::RotorId;
+
+/// Which way the signal is going through a rotor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the entry wheel towards the reflector.
+    Forward,
+    /// From the reflector back towards the entry wheel.
+    Backward,
+}
+
+/// A part of the machine the signal passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Plugboard(Direction),
+    EntryWheel(Direction),
+    /// A rotor, by its slot counting from 0 on the left.
+    Rotor {
+        slot: usize,
+        id: RotorId,
+        direction: Direction,
+    },
+    /// The M4's Greek wheel, which sits between the left rotor and the reflector.
+    GreekRotor {
+        id: RotorId,
+        direction: Direction,
+    },
+    Reflector,
+}
+
+impl Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Component::Plugboard(_) => f.write_str("Plugboard"),
+            Component::EntryWheel(_) => f.write_str("Entry wheel"),
+            Component::Rotor { slot, id, .. } => write!(f, "Rotor {} ({})", slot + 1, id),
+            Component::GreekRotor { id, .. } => write!(f, "Greek wheel ({})", id),
+            Component::Reflector => f.write_str("Reflector"),
+        }
+    }
+}
+
+/// One step of the signal path, from one contact to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    component: Component,
+    input: char,
+    output: char,
+    offset: Option<u8>,
+}
+
+impl Stage {
+    pub(super) fn new(component: Component, input: u8, output: u8, offset: Option<u8>) -> Self {
+        Self {
+            component,
+            input: (input + b'A') as char,
+            output: (output + b'A') as char,
+            offset,
+        }
+    }
+
+    /// Get the component the signal passed through.
+    pub fn component(&self) -> Component {
+        self.component
+    }
+
+    /// Get the contact the signal entered on, as a letter.
+    pub fn input(&self) -> char {
+        self.input
+    }
+
+    /// Get the contact the signal left on, as a letter.
+    pub fn output(&self) -> char {
+        self.output
+    }
+
+    /// For the rotors and reflector, how far the wiring is turned relative to the contacts, which is
+    /// the position less the ring setting.
+    pub fn offset(&self) -> Option<u8> {
+        self.offset
+    }
+}
+
+/// Every intermediate letter of one key press, made by `Enigma::encrypt_traced`. The `Display` impl
+/// renders it as a table, one stage per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    input: char,
+    output: char,
+    positions_before: Vec<u8>,
+    positions_after: Vec<u8>,
+    stages: Vec<Stage>,
+}
+
+impl Trace {
+    pub(super) fn new(
+        input: char,
+        positions_before: Vec<u8>,
+        positions_after: Vec<u8>,
+        stages: Vec<Stage>,
+    ) -> Self {
+        Self {
+            input,
+            output: stages.last().map_or(input, Stage::output),
+            positions_before,
+            positions_after,
+            stages,
+        }
+    }
+
+    /// Get the key that was pressed.
+    pub fn input(&self) -> char {
+        self.input
+    }
+
+    /// Get the lamp that lit up.
+    pub fn output(&self) -> char {
+        self.output
+    }
+
+    /// Get the rotor positions before the key press stepped them, from left to right.
+    pub fn positions_before(&self) -> &[u8] {
+        &self.positions_before
+    }
+
+    /// Get the rotor positions the key press was encrypted at, from left to right.
+    pub fn positions_after(&self) -> &[u8] {
+        &self.positions_after
+    }
+
+    /// Get a reference to each step of the signal path, in order.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let letters = |positions: &[u8]| {
+            positions
+                .iter()
+                .map(|&p| (p + b'A') as char)
+                .collect::<String>()
+        };
+        writeln!(
+            f,
+            "Key {}, rotors stepped from {} to {}",
+            self.input,
+            letters(&self.positions_before),
+            letters(&self.positions_after)
+        )?;
+
+        for stage in &self.stages {
+            // The signal turns round at the reflector.
+            let arrow = match stage.component {
+                Component::Reflector => "<>",
+                _ => "->",
+            };
+            write!(
+                f,
+                "  {:<20} {} {} {}",
+                stage.component.to_string(),
+                stage.input,
+                arrow,
+                stage.output
+            )?;
+            if let Some(offset) = stage.offset {
+                write!(f, "  offset {:>2}", offset)?;
+            }
+            writeln!(f)?;
+        }
+
+        write!(f, "Lamp {}", self.output)
+    }
+}
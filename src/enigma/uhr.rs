@@ -0,0 +1,274 @@
+use std::fmt::{Display, Write};
+
+use crate::error::EnigmaError;
+
+// The Uhr's scrambling disc, as the inner contact each outer contact is wired to. Every plug has a
+// keyboard pin and an entry wheel pin. The a-plugs are on the outer contacts, with a-plug i's pins on
+// 4i and 4i + 2. The b-plugs are on the inner contacts, with b-plug i's entry wheel pin on the contact
+// 4i leads to and its keyboard pin on the one 4i + 2 leads to, so setting 00 is an ordinary plugboard.
+const UHR_WIRING: [u8; 40] = [
+    6, 31, 4, 29, 18, 39, 16, 25, 30, 23, 28, 1, 38, 11, 36, 37, 26, 27, 24, 21, 14, 3, 12, 17, 2,
+    7, 0, 33, 10, 35, 8, 5, 22, 19, 20, 13, 34, 15, 32, 9,
+];
+const UHR_INVERSE: [u8; 40] = gen_uhr_inverse();
+
+const CONTACTS: u8 = 40;
+
+const fn gen_uhr_inverse() -> [u8; 40] {
+    let mut inverse = [0; 40];
+
+    let mut i = 0;
+    while i < 40 {
+        inverse[UHR_WIRING[i] as usize] = i as u8;
+        i += 1;
+    }
+
+    inverse
+}
+
+/// The Luftwaffe's Enigma Uhr, a switch box which replaced the plugboard cables. Its 10 cables have a
+/// red a-plug and a black b-plug. Turning the dial to any of its 40 settings which isn't a multiple of
+/// 4 makes the plugboard substitution non-reciprocal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uhr {
+    // The letters each numbered plug is plugged into.
+    a_plugs: [u8; 10],
+    b_plugs: [u8; 10],
+    dial: u8,
+}
+
+impl Uhr {
+    pub const CABLES: usize = 10;
+
+    /// Takes the 10 plug pairs from the key sheet, where the first letter of each pair gets the
+    /// a-plug and the second gets the b-plug of the same numbered cable.
+    pub fn new(pairs: &[(char, char)], dial: u8) -> Result<Self, EnigmaError> {
+        if pairs.len() != Self::CABLES {
+            return Err(EnigmaError::InvalidPairCount {
+                expected: Self::CABLES,
+                found: pairs.len(),
+            });
+        }
+
+        if dial >= CONTACTS {
+            return Err(EnigmaError::InvalidUhrSetting(dial));
+        }
+
+        let mut a_plugs = [0; 10];
+        let mut b_plugs = [0; 10];
+        let mut seen = [false; 26];
+
+        for (i, &(rc1, rc2)) in pairs.iter().enumerate() {
+            // Report positions as if the pairs were written out as a string of letters.
+            for (position, c) in [(i * 2, rc1), (i * 2 + 1, rc2)] {
+                if !c.is_ascii_uppercase() {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position,
+                        character: c,
+                    });
+                }
+
+                let l = c as u8 - b'A';
+                if seen[l as usize] {
+                    return Err(EnigmaError::DuplicateLetter {
+                        position,
+                        letter: c,
+                    });
+                }
+                seen[l as usize] = true;
+            }
+
+            a_plugs[i] = rc1 as u8 - b'A';
+            b_plugs[i] = rc2 as u8 - b'A';
+        }
+
+        Ok(Self {
+            a_plugs,
+            b_plugs,
+            dial,
+        })
+    }
+
+    /// The 10 plug pairs, with the a-plug's letter first.
+    pub fn pairs(&self) -> Vec<(char, char)> {
+        self.a_plugs
+            .iter()
+            .zip(&self.b_plugs)
+            .map(|(a, b)| ((a + b'A') as char, (b + b'A') as char))
+            .collect()
+    }
+
+    pub fn dial(&self) -> u8 {
+        self.dial
+    }
+
+    pub fn set_dial(&mut self, dial: u8) {
+        self.try_set_dial(dial).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Set the dial, if it's in the range 0..40.
+    pub fn try_set_dial(&mut self, dial: u8) -> Result<(), EnigmaError> {
+        if dial >= CONTACTS {
+            return Err(EnigmaError::InvalidUhrSetting(dial));
+        }
+
+        self.dial = dial;
+        Ok(())
+    }
+
+    /// The substitution the Uhr makes on the way into the machine. Letters without a plug are
+    /// passed through unchanged.
+    pub fn wiring(&self) -> [u8; 26] {
+        let mut wiring = [0; 26];
+        wiring.iter_mut().zip(0..).for_each(|(w, i)| *w = i);
+
+        let d = self.dial;
+        for cable in 0..Self::CABLES {
+            // From an a-plug's keyboard pin, through the disc, to some b-plug's entry wheel pin.
+            let outer = (cable as u8 * 4 + d) % CONTACTS;
+            let inner = (UHR_WIRING[outer as usize] + CONTACTS - d) % CONTACTS;
+            let b_cable = Self::b_cable(inner);
+            wiring[self.a_plugs[cable] as usize] = self.b_plugs[b_cable];
+
+            // And from a b-plug's keyboard pin, back through the disc, to some a-plug's entry wheel pin.
+            let inner = (UHR_WIRING[cable * 4 + 2] + d) % CONTACTS;
+            let outer = (UHR_INVERSE[inner as usize] + CONTACTS - d) % CONTACTS;
+            wiring[self.b_plugs[cable] as usize] = self.a_plugs[outer as usize / 4];
+        }
+
+        wiring
+    }
+
+    // Finds which cable's b-plug has its entry wheel pin on the given inner contact.
+    fn b_cable(inner: u8) -> usize {
+        (0..Self::CABLES)
+            .find(|&cable| UHR_WIRING[cable * 4] == inner)
+            .expect("Uhr disc only connects keyboard pins to entry wheel pins")
+    }
+}
+
+impl Display for Uhr {
+    /// Prints the dial setting followed by the plug pairs, such as `Uhr 27: AB CD ...`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Uhr {:02}:", self.dial)?;
+        for (a, b) in self.pairs() {
+            f.write_char(' ')?;
+            f.write_char(a)?;
+            f.write_char(b)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{Enigma, EnigmaKey, ReflectorId};
+
+    const PAIRS: &str = "AT BL DF GJ HM NW OP QY RZ VX";
+
+    fn uhr(dial: u8) -> Uhr {
+        let pairs: Vec<_> = PAIRS
+            .split(' ')
+            .map(|p| (p.as_bytes()[0] as char, p.as_bytes()[1] as char))
+            .collect();
+        Uhr::new(&pairs, dial).unwrap()
+    }
+
+    fn is_reciprocal(wiring: &[u8; 26]) -> bool {
+        (0..26).all(|c| wiring[wiring[c] as usize] as usize == c)
+    }
+
+    #[test]
+    fn multiples_of_four_are_reciprocal() {
+        // At 00 each cable just swaps its own two letters.
+        let wiring = uhr(0).wiring();
+        for (a, b) in uhr(0).pairs() {
+            assert_eq!(wiring[(a as u8 - b'A') as usize], b as u8 - b'A');
+        }
+
+        for dial in (0..40).step_by(4) {
+            assert!(is_reciprocal(&uhr(dial).wiring()), "Uhr {:02}", dial);
+        }
+    }
+
+    #[test]
+    fn other_settings_are_not_reciprocal() {
+        for dial in (0..40).filter(|d| d % 4 != 0) {
+            let wiring = uhr(dial).wiring();
+            assert!(!is_reciprocal(&wiring), "Uhr {:02}", dial);
+
+            // It's still a permutation, and unplugged letters are left alone.
+            let mut seen = [false; 26];
+            wiring.iter().for_each(|&w| seen[w as usize] = true);
+            assert!(seen.iter().all(|&s| s));
+            for c in [b'C', b'E', b'I', b'K', b'S', b'U'] {
+                assert_eq!(wiring[(c - b'A') as usize], c - b'A');
+            }
+        }
+    }
+
+    #[test]
+    fn machine_round_trips() {
+        let key: EnigmaKey = format!("I IV III / 16 26 8 / 13 1 12 / Uhr 27: {}", PAIRS)
+            .parse()
+            .unwrap();
+        let text = "KEINEBESONDERENEREIGNISSEXFLIEGERKORPS";
+
+        let cipher = Enigma::new(key, ReflectorId::B)
+            .try_encrypt_str(text)
+            .unwrap();
+        assert_eq!(
+            Enigma::new(key, ReflectorId::B)
+                .try_encrypt_str(&cipher)
+                .unwrap(),
+            text
+        );
+
+        // The same cables at 00 give a different cipher text.
+        let plain: EnigmaKey = format!("I IV III / 16 26 8 / 13 1 12 / {}", PAIRS)
+            .parse()
+            .unwrap();
+        assert_ne!(
+            Enigma::new(plain, ReflectorId::B)
+                .try_encrypt_str(text)
+                .unwrap(),
+            cipher
+        );
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert_eq!(
+            Uhr::new(&[('A', 'B')], 0),
+            Err(EnigmaError::InvalidPairCount {
+                expected: 10,
+                found: 1
+            })
+        );
+        assert_eq!(
+            Uhr::new(&uhr(0).pairs(), 40),
+            Err(EnigmaError::InvalidUhrSetting(40))
+        );
+
+        let mut pairs = uhr(0).pairs();
+        pairs[9] = ('V', 'A');
+        assert_eq!(
+            Uhr::new(&pairs, 0),
+            Err(EnigmaError::DuplicateLetter {
+                position: 19,
+                letter: 'A'
+            })
+        );
+        assert_eq!(
+            uhr(0).try_set_dial(40),
+            Err(EnigmaError::InvalidUhrSetting(40))
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(uhr(7).to_string(), format!("Uhr 07: {}", PAIRS));
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use crate::error::EnigmaError;
+
+// The UKW-D's sockets were labelled differently to the rest of the machine. J and Y are the fixed pair,
+// and the remaining labels run backwards around the contacts from Z. This maps each label to the
+// contact it's actually on, so J-Y is the B-O pair you'd see in the Bletchley Park notation.
+const LABEL_TO_CONTACT: [u8; 26] = gen_label_to_contact();
+const CONTACT_TO_LABEL: [u8; 26] = gen_contact_to_label();
+
+const FIXED_PAIR: (u8, u8) = (b'J' - b'A', b'Y' - b'A');
+
+const fn gen_label_to_contact() -> [u8; 26] {
+    let mut mapping = *b"AZYXWVUTSBRQPNMLKJIHGFEDOC";
+
+    let mut i = 0;
+    while i < 26 {
+        mapping[i] -= b'A';
+        i += 1;
+    }
+
+    mapping
+}
+
+const fn gen_contact_to_label() -> [u8; 26] {
+    let label_to_contact = gen_label_to_contact();
+    let mut mapping = [0; 26];
+
+    let mut i = 0;
+    while i < 26 {
+        mapping[label_to_contact[i] as usize] = i as u8;
+        i += 1;
+    }
+
+    mapping
+}
+
+/// The field-rewirable reflector introduced by the Luftwaffe in 1944. It's wired with 12 plug pairs,
+/// given in the German labelling of the sockets, plus the fixed J-Y pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UkwD {
+    // Stored in the machine's contact order, not the UKW-D labels.
+    wiring: [u8; 26],
+}
+
+impl UkwD {
+    pub const PAIRS: usize = 12;
+
+    pub fn new(pairs: &[(char, char)]) -> Result<Self, EnigmaError> {
+        if pairs.len() != Self::PAIRS {
+            return Err(EnigmaError::InvalidPairCount {
+                expected: Self::PAIRS,
+                found: pairs.len(),
+            });
+        }
+
+        let mut wiring = [0; 26];
+        let mut seen = [false; 26];
+
+        let (j, y) = FIXED_PAIR;
+        Self::connect(&mut wiring, j, y);
+
+        for (i, &(rc1, rc2)) in pairs.iter().enumerate() {
+            // Report positions as if the pairs were written out as a string of letters.
+            for (position, c) in [(i * 2, rc1), (i * 2 + 1, rc2)] {
+                // J and Y can't be plugged, they're always connected to each other.
+                if !c.is_ascii_uppercase() || c == 'J' || c == 'Y' {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position,
+                        character: c,
+                    });
+                }
+
+                let l = c as u8 - b'A';
+                if seen[l as usize] {
+                    return Err(EnigmaError::DuplicateLetter {
+                        position,
+                        letter: c,
+                    });
+                }
+                seen[l as usize] = true;
+            }
+
+            Self::connect(&mut wiring, rc1 as u8 - b'A', rc2 as u8 - b'A');
+        }
+
+        Ok(Self { wiring })
+    }
+
+    fn connect(wiring: &mut [u8; 26], label_a: u8, label_b: u8) {
+        let a = LABEL_TO_CONTACT[label_a as usize];
+        let b = LABEL_TO_CONTACT[label_b as usize];
+        wiring[a as usize] = b;
+        wiring[b as usize] = a;
+    }
+
+    /// Get a reference to the reflector's wiring, in the machine's contact order.
+    pub fn wiring(&self) -> &[u8; 26] {
+        &self.wiring
+    }
+
+    /// The 12 plug pairs in the UKW-D labelling, excluding the fixed J-Y pair.
+    pub fn pairs(&self) -> Vec<(char, char)> {
+        let mut pairs = Vec::with_capacity(Self::PAIRS);
+        let mut seen = [false; 26];
+        seen[FIXED_PAIR.0 as usize] = true;
+        seen[FIXED_PAIR.1 as usize] = true;
+
+        for label in 0..26 {
+            if seen[label as usize] {
+                continue;
+            }
+
+            let contact = LABEL_TO_CONTACT[label as usize];
+            let other = CONTACT_TO_LABEL[self.wiring[contact as usize] as usize];
+            seen[label as usize] = true;
+            seen[other as usize] = true;
+
+            pairs.push(((label + b'A') as char, (other + b'A') as char));
+        }
+
+        pairs
+    }
+}
+
+impl FromStr for UkwD {
+    type Err = EnigmaError;
+
+    /// Parses 12 whitespace separated plug pairs in the UKW-D labelling, such as the output of
+    /// `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = Vec::with_capacity(Self::PAIRS);
+        let mut position = 0;
+
+        for pair in s.split_whitespace() {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(a), Some(b), None) => pairs.push((a, b)),
+                (_, _, Some(c)) => {
+                    return Err(EnigmaError::InvalidCharacter {
+                        position: position + 2,
+                        character: c,
+                    })
+                }
+                _ => {
+                    return Err(EnigmaError::InvalidPairCount {
+                        expected: Self::PAIRS,
+                        found: pairs.len(),
+                    })
+                }
+            }
+            position += 2;
+        }
+
+        Self::new(&pairs)
+    }
+}
+
+impl Display for UkwD {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (a, b)) in self.pairs().into_iter().enumerate() {
+            if i != 0 {
+                f.write_char(' ')?;
+            }
+            f.write_char(a)?;
+            f.write_char(b)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{Enigma, EnigmaKey};
+
+    const PAIRS: &str = "AC BD EF GH IK LM NO PQ RS TU VW XZ";
+
+    #[test]
+    fn reciprocal() {
+        let ukwd: UkwD = PAIRS.parse().unwrap();
+        for (c, &w) in ukwd.wiring().iter().enumerate() {
+            assert_ne!(w as usize, c);
+            assert_eq!(ukwd.wiring()[w as usize] as usize, c);
+        }
+
+        // J-Y is always wired, and is the B-O pair in the machine's contact order.
+        assert_eq!(ukwd.wiring()[1], 14);
+
+        let key: EnigmaKey = "I II III / 3 17 25 / 9 12 4 / AF TV KO BL RW"
+            .parse()
+            .unwrap();
+        let text = "LUFTGAUKOMMANDOFUENFMELDETKEINEBESONDERENVORKOMMNISSE";
+        let cipher = Enigma::new(key, ukwd).try_encrypt_str(text).unwrap();
+        assert_ne!(cipher, text);
+        assert_eq!(
+            Enigma::new(key, ukwd).try_encrypt_str(&cipher).unwrap(),
+            text
+        );
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let ukwd: UkwD = PAIRS.parse().unwrap();
+        assert_eq!(ukwd.to_string(), PAIRS);
+        assert_eq!(ukwd.to_string().parse::<UkwD>(), Ok(ukwd));
+
+        // Pairs can be given in any order and either way round, but print in label order.
+        let shuffled: UkwD = "ZX CA BD FE GH KI LM NO PQ RS TU VW".parse().unwrap();
+        assert_eq!(shuffled, ukwd);
+        assert_eq!(shuffled.to_string(), PAIRS);
+    }
+
+    #[test]
+    fn invalid_pairs() {
+        assert_eq!(
+            "AC BD EF GH IK LM NO PQ RS TU VW".parse::<UkwD>(),
+            Err(EnigmaError::InvalidPairCount {
+                expected: 12,
+                found: 11
+            })
+        );
+        assert_eq!(
+            "AJ BD EF GH IK LM NO PQ RS TU VW XZ".parse::<UkwD>(),
+            Err(EnigmaError::InvalidCharacter {
+                position: 1,
+                character: 'J'
+            })
+        );
+        assert_eq!(
+            "AC BD EF GH IK LM NO PQ RS TU VW XA".parse::<UkwD>(),
+            Err(EnigmaError::DuplicateLetter {
+                position: 23,
+                letter: 'A'
+            })
+        );
+    }
+}
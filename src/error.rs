@@ -0,0 +1,356 @@
+use std::fmt::Display;
+
+/// Everything that can go wrong when building a machine or its parts from user input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnigmaError {
+    /// The wiring string wasn't 26 letters long.
+    InvalidLength(usize),
+    /// The input contained something other than an uppercase ASCII letter.
+    InvalidCharacter { position: usize, character: char },
+    /// A letter in the machine's internal alphabet was outside the range 0..26.
+    InvalidLetter { position: usize, letter: u8 },
+    /// The letter was already used earlier in the input, such as a wiring that isn't a permutation
+    /// or a letter plugged twice.
+    DuplicateLetter { position: usize, letter: char },
+    /// A notch position was outside the range 0..26.
+    InvalidNotch { position: usize, notch: u8 },
+    /// A permutation wasn't written as cycles of uppercase letters, such as `(AFK)(BX)`.
+    InvalidCycles(String),
+    /// A reflector must pair every letter with a different letter, which it pairs back.
+    NotInvolution { letter: char, mapped: char },
+    /// The UKW-D must be wired with exactly 12 plug pairs, and the Uhr with 10.
+    InvalidPairCount { expected: usize, found: usize },
+    /// The Uhr's dial only has the settings 0..40.
+    InvalidUhrSetting(u8),
+    /// A rotor or reflector position was outside the range 0..26.
+    InvalidPosition(u8),
+    /// A rotor or reflector ring setting was outside the range 0..26.
+    InvalidRingSetting(u8),
+    /// An ngram table line wasn't of the form `KEY,value`, with a key of N uppercase letters.
+    /// Lines are counted from 0.
+    InvalidNgram { line: usize, entry: String },
+    /// There's no built-in rotor with this name.
+    UnknownRotor(String),
+    /// There's no built-in reflector with this name.
+    UnknownReflector(String),
+    /// A custom rotor or reflector was given the name of a built-in one, which it would be parsed
+    /// back as.
+    ReservedName(String),
+    /// A position or ring setting wasn't a number from 1 to 26, or a letter.
+    InvalidSetting(String),
+    /// A key wasn't of the form `rotors / positions / rings / plugs`, with a position and ring
+    /// setting for every rotor.
+    InvalidKey(String),
+    /// A message indicator wasn't in the form the procedure expected.
+    InvalidIndicator(String),
+    /// A doubled indicator disagreed with the earlier ones about where a letter goes in AD, BE or
+    /// CF, which usually means it was garbled in transmission.
+    ConflictingIndicator(String),
+    /// The indicators didn't show where this letter goes in one of AD, BE and CF. With randomly
+    /// chosen message keys, it takes over a hundred indicators to see every letter.
+    IncompleteCharacteristic(char),
+    /// The two copies of a doubled message key didn't decrypt to the same letters.
+    IndicatorMismatch { first: String, second: String },
+    /// A bigram table entry wasn't a pair of uppercase letters.
+    InvalidBigram { position: usize, bigram: String },
+    /// The bigram was already listed earlier in the bigram table.
+    DuplicateBigram { position: usize, bigram: String },
+    /// The bigram table has no substitution for this bigram.
+    MissingBigram(String),
+    /// A Kenngruppe wasn't three uppercase letters, was allocated more than once, or isn't in the
+    /// Kenngruppenbuch.
+    InvalidKenngruppe(String),
+    /// Part of a radio message's header couldn't be parsed.
+    InvalidRadioMessage(String),
+    /// A radio message's text can't be split into groups of no letters.
+    InvalidGroupSize(usize),
+    /// The letter count in a radio message's header didn't match its text.
+    LetterCountMismatch { expected: usize, found: usize },
+    /// There were more letters than key presses recorded in a `ScramblerTables`.
+    TooManyLetters { recorded: usize, found: usize },
+    /// A stream had a byte other than an ASCII letter, and was set to reject them. Bytes are
+    /// counted from 0 from the start of the stream.
+    InvalidByte { position: usize, byte: u8 },
+    /// A group of a radio message's text wasn't the size of the first group, and wasn't a shorter
+    /// last group. Groups are counted from 0.
+    UnevenGroup { position: usize, group: String },
+}
+
+impl Display for EnigmaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnigmaError::InvalidLength(len) => {
+                write!(f, "Wiring must be 26 letters long, found {}", len)
+            }
+            EnigmaError::InvalidCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "Invalid character {:?} at position {}",
+                character, position
+            ),
+            EnigmaError::InvalidLetter { position, letter } => {
+                write!(f, "Invalid letter {} at position {}", letter, position)
+            }
+            EnigmaError::DuplicateLetter { position, letter } => {
+                write!(f, "Duplicate letter {:?} at position {}", letter, position)
+            }
+            EnigmaError::InvalidNotch { position, notch } => {
+                write!(f, "Invalid notch {} at position {}", notch, position)
+            }
+            EnigmaError::NotInvolution { letter, mapped } => write!(
+                f,
+                "Reflector maps {:?} to {:?}, which doesn't map back",
+                letter, mapped
+            ),
+            EnigmaError::InvalidCycles(cycles) => write!(f, "Invalid cycle notation {:?}", cycles),
+            EnigmaError::InvalidPairCount { expected, found } => {
+                write!(f, "Expected {} plug pairs, found {}", expected, found)
+            }
+            EnigmaError::InvalidUhrSetting(dial) => write!(f, "Invalid Uhr setting {}", dial),
+            EnigmaError::InvalidPosition(position) => {
+                write!(f, "Invalid position {}", position)
+            }
+            EnigmaError::InvalidRingSetting(ring_setting) => {
+                write!(f, "Invalid ring setting {}", ring_setting)
+            }
+            EnigmaError::InvalidNgram { line, entry } => {
+                write!(f, "Invalid ngram entry {:?} on line {}", entry, line)
+            }
+            EnigmaError::UnknownRotor(name) => write!(f, "Unknown rotor {:?}", name),
+            EnigmaError::UnknownReflector(name) => write!(f, "Unknown reflector {:?}", name),
+            EnigmaError::ReservedName(name) => {
+                write!(f, "The name {:?} belongs to a built-in part", name)
+            }
+            EnigmaError::InvalidSetting(setting) => write!(f, "Invalid setting {:?}", setting),
+            EnigmaError::InvalidKey(key) => write!(f, "Invalid key {:?}", key),
+            EnigmaError::InvalidIndicator(indicator) => {
+                write!(f, "Invalid indicator {:?}", indicator)
+            }
+            EnigmaError::ConflictingIndicator(indicator) => {
+                write!(
+                    f,
+                    "Indicator {:?} conflicts with earlier indicators",
+                    indicator
+                )
+            }
+            EnigmaError::IncompleteCharacteristic(letter) => {
+                write!(f, "Not enough indicators to place the letter {:?}", letter)
+            }
+            EnigmaError::IndicatorMismatch { first, second } => write!(
+                f,
+                "Doubled message key decrypted to {:?} and {:?}",
+                first, second
+            ),
+            EnigmaError::InvalidBigram { position, bigram } => {
+                write!(f, "Invalid bigram {:?} at position {}", bigram, position)
+            }
+            EnigmaError::DuplicateBigram { position, bigram } => {
+                write!(f, "Duplicate bigram {:?} at position {}", bigram, position)
+            }
+            EnigmaError::MissingBigram(bigram) => {
+                write!(f, "No substitution for bigram {:?}", bigram)
+            }
+            EnigmaError::InvalidKenngruppe(kenngruppe) => {
+                write!(f, "Invalid Kenngruppe {:?}", kenngruppe)
+            }
+            EnigmaError::InvalidRadioMessage(part) => {
+                write!(f, "Invalid radio message header {:?}", part)
+            }
+            EnigmaError::InvalidGroupSize(size) => write!(f, "Invalid group size {}", size),
+            EnigmaError::LetterCountMismatch { expected, found } => write!(
+                f,
+                "Header gives {} letters, but the text has {}",
+                expected, found
+            ),
+            EnigmaError::TooManyLetters { recorded, found } => write!(
+                f,
+                "Only {} key presses were recorded, but there are {} letters",
+                recorded, found
+            ),
+            EnigmaError::InvalidByte { position, byte } => {
+                write!(f, "Invalid byte {:#04x} at position {}", byte, position)
+            }
+            EnigmaError::UnevenGroup { position, group } => write!(
+                f,
+                "Group {:?} at position {} isn't the size of the first group",
+                group, position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EnigmaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let cases = [
+            (
+                EnigmaError::InvalidLength(25),
+                "Wiring must be 26 letters long, found 25",
+            ),
+            (
+                EnigmaError::InvalidCharacter {
+                    position: 3,
+                    character: 'a',
+                },
+                "Invalid character 'a' at position 3",
+            ),
+            (
+                EnigmaError::InvalidLetter {
+                    position: 4,
+                    letter: 26,
+                },
+                "Invalid letter 26 at position 4",
+            ),
+            (
+                EnigmaError::DuplicateLetter {
+                    position: 5,
+                    letter: 'E',
+                },
+                "Duplicate letter 'E' at position 5",
+            ),
+            (
+                EnigmaError::InvalidNotch {
+                    position: 1,
+                    notch: 26,
+                },
+                "Invalid notch 26 at position 1",
+            ),
+            (
+                EnigmaError::InvalidCycles("(AB".to_string()),
+                "Invalid cycle notation \"(AB\"",
+            ),
+            (
+                EnigmaError::NotInvolution {
+                    letter: 'A',
+                    mapped: 'B',
+                },
+                "Reflector maps 'A' to 'B', which doesn't map back",
+            ),
+            (
+                EnigmaError::InvalidPairCount {
+                    expected: 12,
+                    found: 11,
+                },
+                "Expected 12 plug pairs, found 11",
+            ),
+            (EnigmaError::InvalidUhrSetting(40), "Invalid Uhr setting 40"),
+            (EnigmaError::InvalidPosition(26), "Invalid position 26"),
+            (
+                EnigmaError::InvalidRingSetting(26),
+                "Invalid ring setting 26",
+            ),
+            (
+                EnigmaError::InvalidNgram {
+                    line: 2,
+                    entry: "TH;1".to_string(),
+                },
+                "Invalid ngram entry \"TH;1\" on line 2",
+            ),
+            (
+                EnigmaError::UnknownRotor("IX".to_string()),
+                "Unknown rotor \"IX\"",
+            ),
+            (
+                EnigmaError::UnknownReflector("E".to_string()),
+                "Unknown reflector \"E\"",
+            ),
+            (
+                EnigmaError::ReservedName("I".to_string()),
+                "The name \"I\" belongs to a built-in part",
+            ),
+            (
+                EnigmaError::InvalidSetting("27".to_string()),
+                "Invalid setting \"27\"",
+            ),
+            (
+                EnigmaError::InvalidKey("I II III".to_string()),
+                "Invalid key \"I II III\"",
+            ),
+            (
+                EnigmaError::InvalidIndicator("AB".to_string()),
+                "Invalid indicator \"AB\"",
+            ),
+            (
+                EnigmaError::ConflictingIndicator("ABCDEF".to_string()),
+                "Indicator \"ABCDEF\" conflicts with earlier indicators",
+            ),
+            (
+                EnigmaError::IncompleteCharacteristic('Q'),
+                "Not enough indicators to place the letter 'Q'",
+            ),
+            (
+                EnigmaError::IndicatorMismatch {
+                    first: "ABC".to_string(),
+                    second: "ABD".to_string(),
+                },
+                "Doubled message key decrypted to \"ABC\" and \"ABD\"",
+            ),
+            (
+                EnigmaError::InvalidBigram {
+                    position: 7,
+                    bigram: "A1".to_string(),
+                },
+                "Invalid bigram \"A1\" at position 7",
+            ),
+            (
+                EnigmaError::DuplicateBigram {
+                    position: 8,
+                    bigram: "AA".to_string(),
+                },
+                "Duplicate bigram \"AA\" at position 8",
+            ),
+            (
+                EnigmaError::MissingBigram("QX".to_string()),
+                "No substitution for bigram \"QX\"",
+            ),
+            (
+                EnigmaError::InvalidKenngruppe("AB".to_string()),
+                "Invalid Kenngruppe \"AB\"",
+            ),
+            (
+                EnigmaError::InvalidRadioMessage("1230".to_string()),
+                "Invalid radio message header \"1230\"",
+            ),
+            (EnigmaError::InvalidGroupSize(0), "Invalid group size 0"),
+            (
+                EnigmaError::LetterCountMismatch {
+                    expected: 10,
+                    found: 9,
+                },
+                "Header gives 10 letters, but the text has 9",
+            ),
+            (
+                EnigmaError::TooManyLetters {
+                    recorded: 10,
+                    found: 11,
+                },
+                "Only 10 key presses were recorded, but there are 11 letters",
+            ),
+            (
+                EnigmaError::InvalidByte {
+                    position: 3,
+                    byte: 0xc3,
+                },
+                "Invalid byte 0xc3 at position 3",
+            ),
+            (
+                EnigmaError::UnevenGroup {
+                    position: 1,
+                    group: "ABC".to_string(),
+                },
+                "Group \"ABC\" at position 1 isn't the size of the first group",
+            ),
+        ];
+
+        for (error, text) in cases {
+            assert_eq!(error.to_string(), text);
+        }
+    }
+}
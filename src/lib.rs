@@ -1,2 +1,9 @@
 pub mod analysis;
 pub mod enigma;
+pub mod error;
+pub mod procedure;
+pub mod radio;
+pub mod text;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
@@ -0,0 +1,523 @@
+pub mod naval;
+
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use crate::{
+    enigma::{stepping::Stepping, Enigma, EnigmaKey, Reflector},
+    error::EnigmaError,
+};
+
+/// How the message key was sent alongside a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indicator {
+    /// Before May 1940, the message key was typed twice at the day's Grundstellung, and the six
+    /// letters were sent at the start of the message.
+    Doubled(String),
+    /// From September 1938, the operator picked their own Grundstellung and sent it in the clear,
+    /// followed by the message key typed twice at that Grundstellung.
+    DoubledAt {
+        grundstellung: String,
+        doubled_key: String,
+    },
+    /// From May 1940, the operator picked their own Grundstellung and sent it in the clear, followed
+    /// by the message key typed once at that Grundstellung.
+    Single {
+        grundstellung: String,
+        message_key: String,
+    },
+}
+
+impl Display for Indicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Indicator::Doubled(doubled_key) => f.write_str(doubled_key),
+            Indicator::DoubledAt {
+                grundstellung,
+                doubled_key,
+            } => write!(f, "{} {}", grundstellung, doubled_key),
+            Indicator::Single {
+                grundstellung,
+                message_key,
+            } => write!(f, "{} {}", grundstellung, message_key),
+        }
+    }
+}
+
+impl FromStr for Indicator {
+    type Err = EnigmaError;
+
+    /// Parses the format written by `Display`: either the doubled key, or the Grundstellung and the
+    /// enciphered message key separated by whitespace. Settings are 3 letters, or 4 with a Greek
+    /// wheel, and the message key is taken to be doubled if it's twice as long as the Grundstellung.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_setting = |setting: &str| matches!(setting.len(), 3 | 4);
+
+        let mut parts = s.split_whitespace();
+        let indicator = match (parts.next(), parts.next(), parts.next()) {
+            (Some(doubled_key), None, None) if matches!(doubled_key.len(), 6 | 8) => {
+                Indicator::Doubled(doubled_key.to_owned())
+            }
+            (Some(grundstellung), Some(message_key), None)
+                if is_setting(grundstellung) && grundstellung.len() == message_key.len() =>
+            {
+                Indicator::Single {
+                    grundstellung: grundstellung.to_owned(),
+                    message_key: message_key.to_owned(),
+                }
+            }
+            (Some(grundstellung), Some(doubled_key), None)
+                if is_setting(grundstellung) && grundstellung.len() * 2 == doubled_key.len() =>
+            {
+                Indicator::DoubledAt {
+                    grundstellung: grundstellung.to_owned(),
+                    doubled_key: doubled_key.to_owned(),
+                }
+            }
+            _ => return Err(EnigmaError::InvalidIndicator(s.to_owned())),
+        };
+
+        let letters = indicator.to_string();
+        if let Some((position, character)) = letters
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .enumerate()
+            .find(|(_, c)| !c.is_ascii_uppercase())
+        {
+            return Err(EnigmaError::InvalidCharacter {
+                position,
+                character,
+            });
+        }
+
+        Ok(indicator)
+    }
+}
+
+/// An enciphered message, as handed to the radio operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    indicator: Indicator,
+    text: String,
+}
+
+impl Message {
+    pub fn new(indicator: Indicator, text: String) -> Self {
+        Self { indicator, text }
+    }
+
+    /// Get a reference to the message's indicator.
+    pub fn indicator(&self) -> &Indicator {
+        &self.indicator
+    }
+
+    /// Get a reference to the message's ciphertext, excluding the indicator.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.indicator, f)?;
+        f.write_char(' ')?;
+        f.write_str(&self.text)
+    }
+}
+
+/// A deciphered message, along with the message key the operator chose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedMessage {
+    message_key: String,
+    plaintext: String,
+}
+
+impl DecryptedMessage {
+    /// Get a reference to the message key the message was enciphered at.
+    pub fn message_key(&self) -> &str {
+        &self.message_key
+    }
+
+    /// Get a reference to the message's plaintext.
+    pub fn plaintext(&self) -> &str {
+        &self.plaintext
+    }
+}
+
+/// Encrypts a message with the pre-1940 procedure. The message key is doubled and enciphered at the
+/// day's Grundstellung, then the machine is reset to the message key for the text.
+/// Settings are given as letters, one per rotor including the Greek wheel, such as "WZA". The rotors
+/// move as `stepping` drives them, such as `Ratchet` for the military machines.
+pub fn encrypt_pre_1940<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    grundstellung: &str,
+    message_key: &str,
+    plaintext: &str,
+) -> Result<Message, EnigmaError> {
+    let reflector = reflector.into();
+
+    let doubled_key = encrypt_doubled(key, reflector, stepping, grundstellung, message_key)?;
+    let text = at_setting(key, reflector, stepping, message_key)?.try_encrypt_str(plaintext)?;
+
+    Ok(Message::new(Indicator::Doubled(doubled_key), text))
+}
+
+/// Decrypts a message sent with the pre-1940 procedure. Fails if the two copies of the message key
+/// don't agree, which usually means the indicator was garbled in transmission.
+pub fn decrypt_pre_1940<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    grundstellung: &str,
+    message: &Message,
+) -> Result<DecryptedMessage, EnigmaError> {
+    let reflector = reflector.into();
+
+    let doubled_key = match &message.indicator {
+        Indicator::Doubled(doubled_key) => doubled_key,
+        indicator => return Err(EnigmaError::InvalidIndicator(indicator.to_string())),
+    };
+
+    let message_key = decrypt_doubled(key, reflector, stepping, grundstellung, doubled_key)?;
+    let plaintext =
+        at_setting(key, reflector, stepping, &message_key)?.try_encrypt_str(&message.text)?;
+
+    Ok(DecryptedMessage {
+        message_key,
+        plaintext,
+    })
+}
+
+/// Encrypts a message with the procedure used from September 1938 until May 1940. The operator's
+/// own Grundstellung is sent in the clear, and the message key is doubled and enciphered at it.
+/// Settings are given as letters, one per rotor including the Greek wheel, such as "WZA".
+pub fn encrypt_1938<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    grundstellung: &str,
+    message_key: &str,
+    plaintext: &str,
+) -> Result<Message, EnigmaError> {
+    let reflector = reflector.into();
+
+    let indicator = Indicator::DoubledAt {
+        grundstellung: grundstellung.to_owned(),
+        doubled_key: encrypt_doubled(key, reflector, stepping, grundstellung, message_key)?,
+    };
+    let text = at_setting(key, reflector, stepping, message_key)?.try_encrypt_str(plaintext)?;
+
+    Ok(Message::new(indicator, text))
+}
+
+/// Decrypts a message sent with the procedure used from September 1938 until May 1940. Fails if the
+/// two copies of the message key don't agree.
+pub fn decrypt_1938<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    message: &Message,
+) -> Result<DecryptedMessage, EnigmaError> {
+    let reflector = reflector.into();
+
+    let (grundstellung, doubled_key) = match &message.indicator {
+        Indicator::DoubledAt {
+            grundstellung,
+            doubled_key,
+        } => (grundstellung, doubled_key),
+        indicator => return Err(EnigmaError::InvalidIndicator(indicator.to_string())),
+    };
+
+    let message_key = decrypt_doubled(key, reflector, stepping, grundstellung, doubled_key)?;
+    let plaintext =
+        at_setting(key, reflector, stepping, &message_key)?.try_encrypt_str(&message.text)?;
+
+    Ok(DecryptedMessage {
+        message_key,
+        plaintext,
+    })
+}
+
+/// Encrypts a message with the post-1940 procedure. The operator's own Grundstellung is sent in the
+/// clear, and the message key is enciphered once at it.
+/// Settings are given as letters, one per rotor including the Greek wheel, such as "WZA".
+pub fn encrypt_post_1940<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    grundstellung: &str,
+    message_key: &str,
+    plaintext: &str,
+) -> Result<Message, EnigmaError> {
+    let reflector = reflector.into();
+
+    let indicator = Indicator::Single {
+        grundstellung: grundstellung.to_owned(),
+        message_key: at_setting(key, reflector, stepping, grundstellung)?
+            .try_encrypt_str(message_key)?,
+    };
+    let text = at_setting(key, reflector, stepping, message_key)?.try_encrypt_str(plaintext)?;
+
+    Ok(Message::new(indicator, text))
+}
+
+/// Decrypts a message sent with the post-1940 procedure.
+pub fn decrypt_post_1940<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    stepping: S,
+    message: &Message,
+) -> Result<DecryptedMessage, EnigmaError> {
+    let reflector = reflector.into();
+
+    let (grundstellung, message_key) = match &message.indicator {
+        Indicator::Single {
+            grundstellung,
+            message_key,
+        } => (grundstellung, message_key),
+        indicator => return Err(EnigmaError::InvalidIndicator(indicator.to_string())),
+    };
+
+    let message_key =
+        at_setting(key, reflector, stepping, grundstellung)?.try_encrypt_str(message_key)?;
+    let plaintext =
+        at_setting(key, reflector, stepping, &message_key)?.try_encrypt_str(&message.text)?;
+
+    Ok(DecryptedMessage {
+        message_key,
+        plaintext,
+    })
+}
+
+/// Types the message key twice at the Grundstellung.
+fn encrypt_doubled<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: Reflector,
+    stepping: S,
+    grundstellung: &str,
+    message_key: &str,
+) -> Result<String, EnigmaError> {
+    let mut doubled_key = String::with_capacity(message_key.len() * 2);
+    doubled_key.push_str(message_key);
+    doubled_key.push_str(message_key);
+
+    at_setting(key, reflector, stepping, grundstellung)?.try_encrypt_str(&doubled_key)
+}
+
+/// Deciphers a doubled message key at the Grundstellung, checking both copies agree.
+fn decrypt_doubled<S: Stepping + Copy>(
+    key: &EnigmaKey,
+    reflector: Reflector,
+    stepping: S,
+    grundstellung: &str,
+    doubled_key: &str,
+) -> Result<String, EnigmaError> {
+    let doubled_key =
+        at_setting(key, reflector, stepping, grundstellung)?.try_encrypt_str(doubled_key)?;
+    let (first, second) = doubled_key.split_at(doubled_key.len() / 2);
+    if first != second {
+        return Err(EnigmaError::IndicatorMismatch {
+            first: first.to_owned(),
+            second: second.to_owned(),
+        });
+    }
+
+    Ok(first.to_owned())
+}
+
+/// Creates a machine from the day's key, with the rotors turned to the given letters.
+fn at_setting<S: Stepping>(
+    key: &EnigmaKey,
+    reflector: Reflector,
+    stepping: S,
+    setting: &str,
+) -> Result<Enigma<S>, EnigmaError> {
+    let mut key = *key;
+    let rotor_count = if key.greek_rotor().is_some() { 4 } else { 3 };
+    if setting.chars().count() != rotor_count {
+        return Err(EnigmaError::InvalidSetting(setting.to_owned()));
+    }
+
+    let mut positions = Vec::with_capacity(rotor_count);
+    for (position, c) in setting.chars().enumerate() {
+        if !c.is_ascii_uppercase() {
+            return Err(EnigmaError::InvalidCharacter {
+                position,
+                character: c,
+            });
+        }
+        positions.push(c as u8 - b'A');
+    }
+
+    let mut positions = positions.into_iter();
+    if let Some(greek_rotor) = key.greek_rotor_mut() {
+        greek_rotor.set_rotor_position(positions.next().unwrap());
+    }
+    key.left_rotor_mut()
+        .set_rotor_position(positions.next().unwrap());
+    key.middle_rotor_mut()
+        .set_rotor_position(positions.next().unwrap());
+    key.right_rotor_mut()
+        .set_rotor_position(positions.next().unwrap());
+
+    Ok(Enigma::with_stepping(key, reflector, stepping))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::{
+        stepping::{Cog, Ratchet},
+        ReflectorId,
+    };
+
+    const TEXT: &str = "ANXKOMMANDIERENDERGENERALXSTELLVERTRETUNGXBEFEHL";
+
+    fn key() -> EnigmaKey {
+        "II V III / 1 1 1 / 8 5 20 / AF TV KO BL RW"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn indicator_forms() {
+        for (s, indicator) in [
+            ("PQRXYZ", Indicator::Doubled("PQRXYZ".to_owned())),
+            ("APQRXYZW", Indicator::Doubled("APQRXYZW".to_owned())),
+            (
+                "WZA PQRXYZ",
+                Indicator::DoubledAt {
+                    grundstellung: "WZA".to_owned(),
+                    doubled_key: "PQRXYZ".to_owned(),
+                },
+            ),
+            (
+                "WZA SXT",
+                Indicator::Single {
+                    grundstellung: "WZA".to_owned(),
+                    message_key: "SXT".to_owned(),
+                },
+            ),
+        ] {
+            assert_eq!(s.parse(), Ok(indicator.clone()));
+            assert_eq!(indicator.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn invalid_indicators() {
+        for s in [
+            "",
+            "AB",
+            "ABCD",
+            "ABCDEFGHIJ",
+            "AB ABCD",
+            "WZA SX",
+            "WZA SXT Q",
+        ] {
+            assert_eq!(
+                s.parse::<Indicator>(),
+                Err(EnigmaError::InvalidIndicator(s.to_owned())),
+                "{:?}",
+                s
+            );
+        }
+        assert_eq!(
+            "WZA sXT".parse::<Indicator>(),
+            Err(EnigmaError::InvalidCharacter {
+                position: 3,
+                character: 's'
+            })
+        );
+    }
+
+    #[test]
+    fn pre_1940_round_trip() {
+        let message =
+            encrypt_pre_1940(&key(), ReflectorId::B, Ratchet, "WZA", "SXT", TEXT).unwrap();
+        assert!(matches!(message.indicator(), Indicator::Doubled(key) if key.len() == 6));
+
+        let decrypted = decrypt_pre_1940(&key(), ReflectorId::B, Ratchet, "WZA", &message).unwrap();
+        assert_eq!(decrypted.message_key(), "SXT");
+        assert_eq!(decrypted.plaintext(), TEXT);
+
+        // The wrong indicator type is rejected rather than misread.
+        assert_eq!(
+            decrypt_1938(&key(), ReflectorId::B, Ratchet, &message),
+            Err(EnigmaError::InvalidIndicator(
+                message.indicator().to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn garbled_indicator() {
+        let message = encrypt_1938(&key(), ReflectorId::B, Ratchet, "WZA", "SXT", TEXT).unwrap();
+        let doubled_key = match message.indicator() {
+            Indicator::DoubledAt { doubled_key, .. } => doubled_key,
+            indicator => panic!("Unexpected indicator {}", indicator),
+        };
+
+        // Change the last letter, so the second copy of the key disagrees with the first.
+        let mut garbled = doubled_key[..5].to_owned();
+        garbled.push(if doubled_key.ends_with('A') { 'B' } else { 'A' });
+        let garbled = Message::new(
+            Indicator::DoubledAt {
+                grundstellung: "WZA".to_owned(),
+                doubled_key: garbled,
+            },
+            message.text().to_owned(),
+        );
+
+        match decrypt_1938(&key(), ReflectorId::B, Ratchet, &garbled) {
+            Err(EnigmaError::IndicatorMismatch { first, second }) => {
+                assert_eq!(first, "SXT");
+                assert_eq!(&second[..2], "SX");
+                assert_ne!(second, "SXT");
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn round_trips_1938_and_post_1940() {
+        let message = encrypt_1938(&key(), ReflectorId::B, Ratchet, "WZA", "SXT", TEXT).unwrap();
+        let parsed = Message::new(
+            message.indicator().to_string().parse().unwrap(),
+            message.text().to_owned(),
+        );
+        let decrypted = decrypt_1938(&key(), ReflectorId::B, Ratchet, &parsed).unwrap();
+        assert_eq!(decrypted.message_key(), "SXT");
+        assert_eq!(decrypted.plaintext(), TEXT);
+
+        let message =
+            encrypt_post_1940(&key(), ReflectorId::B, Ratchet, "WZA", "SXT", TEXT).unwrap();
+        let decrypted = decrypt_post_1940(&key(), ReflectorId::B, Ratchet, &message).unwrap();
+        assert_eq!(decrypted.message_key(), "SXT");
+        assert_eq!(decrypted.plaintext(), TEXT);
+
+        // A four letter setting is needed for each rotor of an M4.
+        assert_eq!(
+            encrypt_post_1940(&key(), ReflectorId::B, Ratchet, "AWZA", "SXT", TEXT),
+            Err(EnigmaError::InvalidSetting("AWZA".to_owned()))
+        );
+    }
+
+    #[test]
+    fn other_stepping() {
+        let key: EnigmaKey = "G312-I G312-II G312-III / 1 1 1 / 3 4 5".parse().unwrap();
+        let reflector = Reflector::new(ReflectorId::G312_UKW, 17, 5);
+
+        let message = encrypt_1938(&key, reflector, Cog, "QRS", "SXT", TEXT).unwrap();
+        let decrypted = decrypt_1938(&key, reflector, Cog, &message).unwrap();
+        assert_eq!(decrypted.plaintext(), TEXT);
+
+        // The Enigma G's rotors don't move like the military machines' do.
+        let ratchet = encrypt_1938(&key, reflector, Ratchet, "QRS", "SXT", TEXT).unwrap();
+        assert_ne!(ratchet.text(), message.text());
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use super::at_setting;
+use crate::{
+    enigma::{stepping::Ratchet, EnigmaKey, Reflector},
+    error::EnigmaError,
+};
+
+const BIGRAMS: usize = 26 * 26;
+const UNUSED: u16 = u16::MAX;
+
+/// One of the Kriegsmarine's bigram substitution tables, used to disguise the message indicator.
+/// The tables were reciprocal, so each pair of bigrams substitutes both ways. Reconstructed tables
+/// are often incomplete, so a table doesn't need to cover every bigram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigramTable {
+    substitutions: Vec<u16>,
+}
+
+impl BigramTable {
+    pub fn new(pairs: &[(&str, &str)]) -> Result<Self, EnigmaError> {
+        let mut substitutions = vec![UNUSED; BIGRAMS];
+
+        for (i, &(a, b)) in pairs.iter().enumerate() {
+            // Report positions as if the pairs were written out as a list of bigrams.
+            let ia = Self::index(a, i * 2)?;
+            let ib = Self::index(b, i * 2 + 1)?;
+
+            for (position, bigram, index) in [(i * 2, a, ia), (i * 2 + 1, b, ib)] {
+                if substitutions[index] != UNUSED {
+                    return Err(EnigmaError::DuplicateBigram {
+                        position,
+                        bigram: bigram.to_owned(),
+                    });
+                }
+            }
+
+            substitutions[ia] = ib as u16;
+            substitutions[ib] = ia as u16;
+        }
+
+        Ok(Self { substitutions })
+    }
+
+    fn index(bigram: &str, position: usize) -> Result<usize, EnigmaError> {
+        let mut chars = bigram.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(a), Some(b), None) if a.is_ascii_uppercase() && b.is_ascii_uppercase() => {
+                Ok((a as u8 - b'A') as usize * 26 + (b as u8 - b'A') as usize)
+            }
+            _ => Err(EnigmaError::InvalidBigram {
+                position,
+                bigram: bigram.to_owned(),
+            }),
+        }
+    }
+
+    /// Substitutes the two letters, which must be uppercase ASCII.
+    pub fn substitute(&self, a: char, b: char) -> Result<(char, char), EnigmaError> {
+        let mut bigram = String::with_capacity(2);
+        bigram.push(a);
+        bigram.push(b);
+
+        let index = Self::index(&bigram, 0)?;
+        match self.substitutions[index] {
+            UNUSED => Err(EnigmaError::MissingBigram(bigram)),
+            v => Ok((
+                ((v / 26) as u8 + b'A') as char,
+                ((v % 26) as u8 + b'A') as char,
+            )),
+        }
+    }
+}
+
+impl FromStr for BigramTable {
+    type Err = EnigmaError;
+
+    /// Parses whitespace separated entries of the form `AB=CD`, meaning AB and CD substitute for
+    /// each other. Each bigram should only be listed once.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = Vec::new();
+        for (i, entry) in s.split_whitespace().enumerate() {
+            let (a, b) = entry
+                .split_once('=')
+                .ok_or_else(|| EnigmaError::InvalidBigram {
+                    position: i * 2,
+                    bigram: entry.to_owned(),
+                })?;
+            pairs.push((a, b));
+        }
+
+        Self::new(&pairs)
+    }
+}
+
+/// A Kenngruppenbuch, which lists the Kenngruppen (identifying trigrams) allocated to each key net.
+/// Any of a net's Kenngruppen could be used in an indicator, telling the receiver which key to use.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Kenngruppenbuch {
+    key_nets: HashMap<String, String>,
+}
+
+impl Kenngruppenbuch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates the Kenngruppen to a key net. Each Kenngruppe can only belong to one net, and can
+    /// only be listed once.
+    pub fn add_key_net(&mut self, key_net: &str, kenngruppen: &[&str]) -> Result<(), EnigmaError> {
+        let mut seen = HashSet::new();
+        for &kenngruppe in kenngruppen {
+            let valid = kenngruppe.len() == 3 && kenngruppe.chars().all(|c| c.is_ascii_uppercase());
+            if !valid || self.key_nets.contains_key(kenngruppe) || !seen.insert(kenngruppe) {
+                return Err(EnigmaError::InvalidKenngruppe(kenngruppe.to_owned()));
+            }
+        }
+
+        for &kenngruppe in kenngruppen {
+            self.key_nets
+                .insert(kenngruppe.to_owned(), key_net.to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Finds the key net a Kenngruppe belongs to.
+    pub fn key_net(&self, kenngruppe: &str) -> Option<&str> {
+        self.key_nets.get(kenngruppe).map(String::as_str)
+    }
+}
+
+impl FromStr for Kenngruppenbuch {
+    type Err = EnigmaError;
+
+    /// Parses one key net per line, as its name followed by a colon and its whitespace separated
+    /// Kenngruppen, such as `Heimische Gewaesser: SWQ KLM ZZD`. Blank lines are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut book = Self::new();
+
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (key_net, kenngruppen) = line
+                .split_once(':')
+                .ok_or_else(|| EnigmaError::InvalidKenngruppe(line.to_owned()))?;
+            let kenngruppen = kenngruppen.split_whitespace().collect::<Vec<_>>();
+            book.add_key_net(key_net.trim(), &kenngruppen)?;
+        }
+
+        Ok(book)
+    }
+}
+
+/// The two four-letter groups sent at the start and end of a naval message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavalIndicator {
+    groups: [String; 2],
+}
+
+impl NavalIndicator {
+    /// Builds the indicator from the Kenngruppe and the Verfahrenkenngruppe, the trigram the message
+    /// key is derived from. They're written one above the other, offset by the two filler letters:
+    ///
+    /// ```text
+    /// F K K K
+    /// V V V F
+    /// ```
+    ///
+    /// Then each column is substituted with the bigram table, and the rows become the two groups.
+    pub fn encode(
+        table: &BigramTable,
+        kenngruppe: &str,
+        verfahrenkenngruppe: &str,
+        fillers: (char, char),
+    ) -> Result<Self, EnigmaError> {
+        for trigram in [kenngruppe, verfahrenkenngruppe] {
+            if trigram.len() != 3 || !trigram.chars().all(|c| c.is_ascii_uppercase()) {
+                return Err(EnigmaError::InvalidIndicator(trigram.to_owned()));
+            }
+        }
+
+        let top = std::iter::once(fillers.0).chain(kenngruppe.chars());
+        let bottom = verfahrenkenngruppe
+            .chars()
+            .chain(std::iter::once(fillers.1));
+
+        let mut groups = [String::with_capacity(4), String::with_capacity(4)];
+        for (a, b) in top.zip(bottom) {
+            let (a, b) = table.substitute(a, b)?;
+            groups[0].push(a);
+            groups[1].push(b);
+        }
+
+        Ok(Self { groups })
+    }
+
+    /// Reverses the bigram substitution, returning the Kenngruppe and Verfahrenkenngruppe.
+    pub fn decode(&self, table: &BigramTable) -> Result<(String, String), EnigmaError> {
+        let mut top = String::with_capacity(4);
+        let mut bottom = String::with_capacity(4);
+        for (a, b) in self.groups[0].chars().zip(self.groups[1].chars()) {
+            let (a, b) = table.substitute(a, b)?;
+            top.push(a);
+            bottom.push(b);
+        }
+
+        Ok((top[1..].to_owned(), bottom[..3].to_owned()))
+    }
+
+    /// Get a reference to the indicator's two groups.
+    pub fn groups(&self) -> &[String; 2] {
+        &self.groups
+    }
+}
+
+impl Display for NavalIndicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.groups[0], self.groups[1])
+    }
+}
+
+impl FromStr for NavalIndicator {
+    type Err = EnigmaError;
+
+    /// Parses the two four-letter groups, separated by whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(a), Some(b), None)
+                if [a, b]
+                    .iter()
+                    .all(|g| g.len() == 4 && g.chars().all(|c| c.is_ascii_uppercase())) =>
+            {
+                Ok(Self {
+                    groups: [a.to_owned(), b.to_owned()],
+                })
+            }
+            _ => Err(EnigmaError::InvalidIndicator(s.to_owned())),
+        }
+    }
+}
+
+/// An enciphered naval message. The indicator was sent both before and after the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavalMessage {
+    indicator: NavalIndicator,
+    text: String,
+}
+
+impl NavalMessage {
+    pub fn new(indicator: NavalIndicator, text: String) -> Self {
+        Self { indicator, text }
+    }
+
+    /// Get a reference to the message's indicator.
+    pub fn indicator(&self) -> &NavalIndicator {
+        &self.indicator
+    }
+
+    /// Get a reference to the message's ciphertext, excluding the indicator.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Display for NavalMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.indicator, self.text, self.indicator)
+    }
+}
+
+/// A deciphered naval message, along with the Kenngruppe, its key net and the message key recovered
+/// from its indicator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedNavalMessage {
+    kenngruppe: String,
+    key_net: String,
+    message_key: String,
+    plaintext: String,
+}
+
+impl DecryptedNavalMessage {
+    /// Get a reference to the Kenngruppe, which identifies the key net.
+    pub fn kenngruppe(&self) -> &str {
+        &self.kenngruppe
+    }
+
+    /// Get a reference to the key net the Kenngruppenbuch allocates the Kenngruppe to.
+    pub fn key_net(&self) -> &str {
+        &self.key_net
+    }
+
+    /// Get a reference to the message key the message was enciphered at.
+    pub fn message_key(&self) -> &str {
+        &self.message_key
+    }
+
+    /// Get a reference to the message's plaintext.
+    pub fn plaintext(&self) -> &str {
+        &self.plaintext
+    }
+}
+
+/// Encrypts a message with the naval procedure, given an indicator made with
+/// `NavalIndicator::encode`. The indicator's Kenngruppe must be allocated in the Kenngruppenbuch, so
+/// the receiver can tell which key net the message was sent in. Its Verfahrenkenngruppe is enciphered
+/// at the day's Grundstellung to give the message key.
+/// On the M4 the Grundstellung has four letters, and the Greek wheel stays at its Grundstellung for
+/// the message.
+pub fn encrypt(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    grundstellung: &str,
+    table: &BigramTable,
+    book: &Kenngruppenbuch,
+    indicator: NavalIndicator,
+    plaintext: &str,
+) -> Result<NavalMessage, EnigmaError> {
+    let reflector = reflector.into();
+
+    let (kenngruppe, verfahrenkenngruppe) = indicator.decode(table)?;
+    lookup_key_net(book, &kenngruppe)?;
+    let message_key = message_key(key, reflector, grundstellung, &verfahrenkenngruppe)?;
+    let text = at_setting(key, reflector, Ratchet, &message_key)?.try_encrypt_str(plaintext)?;
+
+    Ok(NavalMessage::new(indicator, text))
+}
+
+/// Decrypts a message sent with the naval procedure. Fails if the indicator's Kenngruppe isn't in the
+/// Kenngruppenbuch, which usually means the indicator was garbled or the wrong bigram table was used.
+pub fn decrypt(
+    key: &EnigmaKey,
+    reflector: impl Into<Reflector>,
+    grundstellung: &str,
+    table: &BigramTable,
+    book: &Kenngruppenbuch,
+    message: &NavalMessage,
+) -> Result<DecryptedNavalMessage, EnigmaError> {
+    let reflector = reflector.into();
+
+    let (kenngruppe, verfahrenkenngruppe) = message.indicator.decode(table)?;
+    let key_net = lookup_key_net(book, &kenngruppe)?.to_owned();
+    let message_key = message_key(key, reflector, grundstellung, &verfahrenkenngruppe)?;
+    let plaintext =
+        at_setting(key, reflector, Ratchet, &message_key)?.try_encrypt_str(&message.text)?;
+
+    Ok(DecryptedNavalMessage {
+        kenngruppe,
+        key_net,
+        message_key,
+        plaintext,
+    })
+}
+
+fn lookup_key_net<'a>(book: &'a Kenngruppenbuch, kenngruppe: &str) -> Result<&'a str, EnigmaError> {
+    book.key_net(kenngruppe)
+        .ok_or_else(|| EnigmaError::InvalidKenngruppe(kenngruppe.to_owned()))
+}
+
+fn message_key(
+    key: &EnigmaKey,
+    reflector: Reflector,
+    grundstellung: &str,
+    verfahrenkenngruppe: &str,
+) -> Result<String, EnigmaError> {
+    let enciphered =
+        at_setting(key, reflector, Ratchet, grundstellung)?.try_encrypt_str(verfahrenkenngruppe)?;
+
+    // The Greek wheel isn't part of the Verfahrenkenngruppe, so it keeps its Grundstellung.
+    let mut message_key = String::with_capacity(4);
+    if key.greek_rotor().is_some() {
+        message_key.extend(grundstellung.chars().next());
+    }
+    message_key.push_str(&enciphered);
+
+    Ok(message_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enigma::ReflectorId;
+
+    // Pairs each bigram with the one after it, AA=AB AC=AD and so on, so every bigram is covered.
+    fn full_table() -> BigramTable {
+        let bigram = |i: usize| {
+            let mut bigram = String::with_capacity(2);
+            bigram.push((b'A' + (i / 26) as u8) as char);
+            bigram.push((b'A' + (i % 26) as u8) as char);
+            bigram
+        };
+        let bigrams: Vec<_> = (0..BIGRAMS).map(bigram).collect();
+        let pairs: Vec<_> = bigrams
+            .chunks_exact(2)
+            .map(|pair| (pair[0].as_str(), pair[1].as_str()))
+            .collect();
+
+        BigramTable::new(&pairs).unwrap()
+    }
+
+    fn book() -> Kenngruppenbuch {
+        "Heimische Gewaesser: SWQ KLM ZZD\nTriton: AVA RXB"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn bigram_table() {
+        let table: BigramTable = "AB=CD EF=GH".parse().unwrap();
+        assert_eq!(table.substitute('A', 'B'), Ok(('C', 'D')));
+        assert_eq!(table.substitute('C', 'D'), Ok(('A', 'B')));
+        assert_eq!(table.substitute('G', 'H'), Ok(('E', 'F')));
+        assert_eq!(
+            table.substitute('A', 'C'),
+            Err(EnigmaError::MissingBigram("AC".to_owned()))
+        );
+
+        assert_eq!(
+            "AB=CD EF".parse::<BigramTable>(),
+            Err(EnigmaError::InvalidBigram {
+                position: 2,
+                bigram: "EF".to_owned()
+            })
+        );
+        assert_eq!(
+            "AB=CD EF=Gh".parse::<BigramTable>(),
+            Err(EnigmaError::InvalidBigram {
+                position: 3,
+                bigram: "Gh".to_owned()
+            })
+        );
+        assert_eq!(
+            "AB=CD EF=AB".parse::<BigramTable>(),
+            Err(EnigmaError::DuplicateBigram {
+                position: 3,
+                bigram: "AB".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn kenngruppenbuch() {
+        let book = book();
+        assert_eq!(book.key_net("KLM"), Some("Heimische Gewaesser"));
+        assert_eq!(book.key_net("RXB"), Some("Triton"));
+        assert_eq!(book.key_net("ABC"), None);
+
+        for s in [
+            "Triton: AVA AVA",
+            "Triton: AV",
+            "Triton AVA",
+            "A: SWQ\nB: SWQ",
+        ] {
+            assert!(matches!(
+                s.parse::<Kenngruppenbuch>(),
+                Err(EnigmaError::InvalidKenngruppe(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn indicator_round_trip() {
+        let table = full_table();
+        let indicator = NavalIndicator::encode(&table, "SWQ", "ABC", ('X', 'Y')).unwrap();
+
+        // F K K K over V V V F gives the columns XA SB WC QY, which substitute to XB SA WD QZ.
+        assert_eq!(indicator.to_string(), "XSWQ BADZ");
+        assert_eq!(indicator.to_string().parse(), Ok(indicator.clone()));
+        assert_eq!(
+            indicator.decode(&table),
+            Ok(("SWQ".to_owned(), "ABC".to_owned()))
+        );
+
+        assert_eq!(
+            NavalIndicator::encode(&table, "SW", "ABC", ('X', 'Y')),
+            Err(EnigmaError::InvalidIndicator("SW".to_owned()))
+        );
+        assert_eq!(
+            "XSWQ BAD".parse::<NavalIndicator>(),
+            Err(EnigmaError::InvalidIndicator("XSWQ BAD".to_owned()))
+        );
+
+        // A reconstructed table with gaps can't substitute every column.
+        let partial: BigramTable = "XA=XB SB=SA WC=WD".parse().unwrap();
+        assert_eq!(
+            NavalIndicator::encode(&partial, "SWQ", "ABC", ('X', 'Y')),
+            Err(EnigmaError::MissingBigram("QY".to_owned()))
+        );
+    }
+
+    #[test]
+    fn m4_round_trip() {
+        let key: EnigmaKey = "Beta II IV I / 1 1 1 1 / 1 1 1 22 / AT BL DF GJ HM NW OP QY RZ VX"
+            .parse()
+            .unwrap();
+        let (table, book) = (full_table(), book());
+        let text =
+            "VONVONJLOOKSJHFFTTTEINSEINSDREIZWOYYQNNSNEUNINHALTXXBEIANGRIFFUNTERWASSERGEDRUECKT";
+
+        let indicator = NavalIndicator::encode(&table, "RXB", "QEZ", ('P', 'K')).unwrap();
+        let message = encrypt(
+            &key,
+            ReflectorId::BThin,
+            "VJNA",
+            &table,
+            &book,
+            indicator,
+            text,
+        )
+        .unwrap();
+        assert_ne!(message.text(), text);
+
+        let decrypted = decrypt(&key, ReflectorId::BThin, "VJNA", &table, &book, &message).unwrap();
+        assert_eq!(decrypted.kenngruppe(), "RXB");
+        assert_eq!(decrypted.key_net(), "Triton");
+        assert_eq!(decrypted.plaintext(), text);
+
+        // The Greek wheel keeps its Grundstellung, and the Verfahrenkenngruppe gives the rest.
+        let mut machine = at_setting(&key, ReflectorId::BThin.into(), Ratchet, "VJNA").unwrap();
+        let rest = machine.try_encrypt_str("QEZ").unwrap();
+        assert_eq!(decrypted.message_key(), format!("V{}", rest));
+    }
+
+    #[test]
+    fn unknown_kenngruppe() {
+        let key: EnigmaKey = "I II III / 1 1 1 / 1 1 1".parse().unwrap();
+        let (table, book) = (full_table(), book());
+
+        let indicator = NavalIndicator::encode(&table, "ABC", "QEZ", ('P', 'K')).unwrap();
+        assert_eq!(
+            encrypt(
+                &key,
+                ReflectorId::B,
+                "JNA",
+                &table,
+                &book,
+                indicator.clone(),
+                "HALLO"
+            ),
+            Err(EnigmaError::InvalidKenngruppe("ABC".to_owned()))
+        );
+
+        let message = NavalMessage::new(indicator, "HALLO".to_owned());
+        assert_eq!(
+            decrypt(&key, ReflectorId::B, "JNA", &table, &book, &message),
+            Err(EnigmaError::InvalidKenngruppe("ABC".to_owned()))
+        );
+    }
+}
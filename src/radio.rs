@@ -0,0 +1,339 @@
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use crate::{
+    error::EnigmaError,
+    procedure::{
+        naval::{NavalIndicator, NavalMessage},
+        Indicator, Message,
+    },
+};
+
+/// A message as it was sent over the radio, such as
+/// `1630 = 3TLE = 1TL = 145 = WZA UHL = RFUGZ EDPUD ...`.
+///
+/// The header has the time of origin, then optionally the number of parts (Teile) and which part
+/// this is, then the number of letters in the text, and then the indicator. The text follows in
+/// groups, usually of five letters, or four in naval traffic.
+///
+/// The indicator is a `procedure::Indicator` for army and air force traffic, or a `NavalIndicator`.
+/// A four-rotor key can give either one as two four-letter groups, so naval traffic should be
+/// parsed as a `RadioMessage<NavalIndicator>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RadioMessage<I = Indicator> {
+    hour: u8,
+    minute: u8,
+    // This part's number, and the number of parts in total.
+    parts: Option<(u8, u8)>,
+    indicator: I,
+    text: String,
+    group_size: usize,
+}
+
+impl<I> RadioMessage<I> {
+    pub fn new(hour: u8, minute: u8, indicator: I, text: String) -> Result<Self, EnigmaError> {
+        if hour >= 24 || minute >= 60 {
+            return Err(EnigmaError::InvalidRadioMessage(format!(
+                "{:02}{:02}",
+                hour, minute
+            )));
+        }
+
+        if let Some((position, character)) = text
+            .chars()
+            .enumerate()
+            .find(|(_, c)| !c.is_ascii_uppercase())
+        {
+            return Err(EnigmaError::InvalidCharacter {
+                position,
+                character,
+            });
+        }
+
+        Ok(Self {
+            hour,
+            minute,
+            parts: None,
+            indicator,
+            text,
+            group_size: 5,
+        })
+    }
+
+    /// Get the message's time of origin, as the hour and minute.
+    pub fn time(&self) -> (u8, u8) {
+        (self.hour, self.minute)
+    }
+
+    /// Get which part of a multi-part message this is, and how many parts there are.
+    pub fn parts(&self) -> Option<(u8, u8)> {
+        self.parts
+    }
+
+    /// Set which part of a multi-part message this is, and how many parts there are. Parts are
+    /// counted from 1.
+    pub fn set_parts(&mut self, part: u8, parts: u8) -> Result<(), EnigmaError> {
+        if part < 1 || part > parts {
+            return Err(EnigmaError::InvalidRadioMessage(format!(
+                "{}TLE = {}TL",
+                parts, part
+            )));
+        }
+
+        self.parts = Some((part, parts));
+        Ok(())
+    }
+
+    /// Get a reference to the message's indicator.
+    pub fn indicator(&self) -> &I {
+        &self.indicator
+    }
+
+    /// Get a reference to the message's ciphertext, without the grouping. This is what should be
+    /// passed to the `analysis` functions.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The number of letters in the text, as sent in the header.
+    pub fn letter_count(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Get the number of letters in each group of the text.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Set the number of letters in each group of the text, which can't be 0.
+    pub fn set_group_size(&mut self, group_size: usize) -> Result<(), EnigmaError> {
+        if group_size == 0 {
+            return Err(EnigmaError::InvalidGroupSize(group_size));
+        }
+
+        self.group_size = group_size;
+        Ok(())
+    }
+}
+
+impl RadioMessage {
+    /// Creates a radio message from the output of one of the `procedure` functions.
+    pub fn from_message(hour: u8, minute: u8, message: &Message) -> Result<Self, EnigmaError> {
+        Self::new(
+            hour,
+            minute,
+            message.indicator().clone(),
+            message.text().to_owned(),
+        )
+    }
+
+    /// Converts the message for one of the `procedure` decryption functions.
+    pub fn to_message(&self) -> Message {
+        Message::new(self.indicator.clone(), self.text.clone())
+    }
+}
+
+impl RadioMessage<NavalIndicator> {
+    /// Creates a radio message from the output of `naval::encrypt`, with the text in groups of four.
+    pub fn from_naval_message(
+        hour: u8,
+        minute: u8,
+        message: &NavalMessage,
+    ) -> Result<Self, EnigmaError> {
+        let mut radio_message = Self::new(
+            hour,
+            minute,
+            message.indicator().clone(),
+            message.text().to_owned(),
+        )?;
+        radio_message.group_size = 4;
+
+        Ok(radio_message)
+    }
+
+    /// Converts the message for `naval::decrypt`.
+    pub fn to_naval_message(&self) -> NavalMessage {
+        NavalMessage::new(self.indicator.clone(), self.text.clone())
+    }
+}
+
+impl<I: FromStr<Err = EnigmaError>> FromStr for RadioMessage<I> {
+    type Err = EnigmaError;
+
+    /// Parses the format written by `Display`. A trailing `=` is allowed, and the group size is taken
+    /// from the first group of the text, which every other group but the last must match. Fails if
+    /// the letter count in the header doesn't match the text.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| EnigmaError::InvalidRadioMessage(part.trim().to_owned());
+
+        let mut sections = s
+            .trim()
+            .trim_end_matches('=')
+            .split('=')
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        let text = sections.pop().ok_or_else(|| invalid(s))?;
+        let (time, parts, count, indicator) = match sections[..] {
+            [time, parts, part, count, indicator] => {
+                let parts = parts
+                    .strip_suffix("TLE")
+                    .and_then(parse_number)
+                    .ok_or_else(|| invalid(parts))?;
+                let part = part
+                    .strip_suffix("TL")
+                    .and_then(parse_number)
+                    .filter(|&p| p >= 1 && p <= parts)
+                    .ok_or_else(|| invalid(part))?;
+                (time, Some((part, parts)), count, indicator)
+            }
+            [time, count, indicator] => (time, None, count, indicator),
+            _ => return Err(invalid(s)),
+        };
+
+        let (hour, minute) = match (time.len(), time.get(..2), time.get(2..)) {
+            (4, Some(hour), Some(minute)) => (
+                parse_number(hour).ok_or_else(|| invalid(time))?,
+                parse_number(minute).ok_or_else(|| invalid(time))?,
+            ),
+            _ => return Err(invalid(time)),
+        };
+        let count = parse_number(count).ok_or_else(|| invalid(count))?;
+
+        let groups = text.split_whitespace().collect::<Vec<_>>();
+        let group_size = groups.first().map_or(5, |group| group.len());
+        // Only the last group can be short.
+        if let Some((position, group)) = groups.iter().enumerate().find(|&(i, group)| {
+            group.len() > group_size || (group.len() < group_size && i + 1 < groups.len())
+        }) {
+            return Err(EnigmaError::UnevenGroup {
+                position,
+                group: (*group).to_owned(),
+            });
+        }
+        let text = groups.concat();
+        if text.len() != count {
+            return Err(EnigmaError::LetterCountMismatch {
+                expected: count,
+                found: text.len(),
+            });
+        }
+
+        let mut message = Self::new(hour, minute, indicator.parse()?, text)?;
+        message.parts = parts;
+        message.group_size = group_size;
+
+        Ok(message)
+    }
+}
+
+/// Parses a number in the header, which is only ever written with ASCII digits.
+fn parse_number<T: FromStr>(s: &str) -> Option<T> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+impl<I: Display> Display for RadioMessage<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}{:02} = ", self.hour, self.minute)?;
+        if let Some((part, parts)) = self.parts {
+            write!(f, "{}TLE = {}TL = ", parts, part)?;
+        }
+        write!(f, "{} = {} =", self.letter_count(), self.indicator)?;
+
+        for group in self.text.as_bytes().chunks(self.group_size) {
+            f.write_char(' ')?;
+            // The text is checked to be ASCII when the message is created.
+            f.write_str(std::str::from_utf8(group).unwrap())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for s in [
+            "1630 = 12 = WZA UHL = RFUGZ EDPUD QB",
+            "0915 = 10 = PQRXYZ = ABCDE FGHIJ",
+            "2359 = 3TLE = 2TL = 7 = WZA UHLUHL = RFU GZE D",
+        ] {
+            let message: RadioMessage = s.parse().unwrap();
+            assert_eq!(message.to_string(), s);
+        }
+
+        let message: RadioMessage = "2359 = 3TLE = 2TL = 7 = WZA UHLUHL = RFU GZE D ="
+            .parse()
+            .unwrap();
+        assert_eq!(message.time(), (23, 59));
+        assert_eq!(message.parts(), Some((2, 3)));
+        assert_eq!(message.group_size(), 3);
+        assert_eq!(message.text(), "RFUGZED");
+        assert!(matches!(message.indicator(), Indicator::DoubledAt { .. }));
+
+        // A naval indicator is two groups of four, which an M4 key's Single indicator would also be.
+        let s = "1205 = 8 = XSWQ BADZ = ABCD EFGH";
+        let message: RadioMessage<NavalIndicator> = s.parse().unwrap();
+        assert_eq!(message.indicator().groups(), &["XSWQ", "BADZ"]);
+        assert_eq!(message.to_string(), s);
+        assert_eq!(
+            message.to_naval_message().to_string(),
+            "XSWQ BADZ ABCDEFGH XSWQ BADZ"
+        );
+    }
+
+    #[test]
+    fn letter_count_mismatch() {
+        assert_eq!(
+            "1630 = 11 = WZA UHL = RFUGZ EDPUD QB".parse::<RadioMessage>(),
+            Err(EnigmaError::LetterCountMismatch {
+                expected: 11,
+                found: 12
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let invalid = |part: &str| Err(EnigmaError::InvalidRadioMessage(part.to_owned()));
+
+        for (s, part) in [
+            ("+1+2 = 5 = WZA UHL = RFUGZ", "+1+2"),
+            ("163 = 5 = WZA UHL = RFUGZ", "163"),
+            ("2460 = 5 = WZA UHL = RFUGZ", "2460"),
+            ("1630 = +5 = WZA UHL = RFUGZ", "+5"),
+            ("1630 = +3TLE = 1TL = 5 = WZA UHL = RFUGZ", "+3TLE"),
+            ("1630 = 3TLE = 4TL = 5 = WZA UHL = RFUGZ", "4TL"),
+            ("1630 = WZA UHL = RFUGZ", "1630 = WZA UHL = RFUGZ"),
+        ] {
+            assert_eq!(s.parse::<RadioMessage>(), invalid(part), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn uneven_groups() {
+        assert_eq!(
+            "1630 = 12 = WZA UHL = RFUGZ EDP UDQB".parse::<RadioMessage>(),
+            Err(EnigmaError::UnevenGroup {
+                position: 1,
+                group: "EDP".to_owned()
+            })
+        );
+        assert_eq!(
+            "1630 = 12 = WZA UHL = RFUGZ EDPUDQ B".parse::<RadioMessage>(),
+            Err(EnigmaError::UnevenGroup {
+                position: 1,
+                group: "EDPUDQ".to_owned()
+            })
+        );
+    }
+}
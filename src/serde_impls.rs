@@ -0,0 +1,141 @@
+// Everything is stored as the same text as its `Display` impl, such as `II V III / 8 5 20 / 13 3 21 /
+// AF TV` for a key, and read back with its `FromStr` impl. That keeps the stored form readable, and
+// independent of how the types are laid out in memory. Custom rotors and reflectors can't be read
+// back, as only their names are stored. They can't share a name with a built-in part, so reading one
+// fails rather than quietly giving the built-in wiring.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::enigma::{
+    permutation::Permutation, ukwd::UkwD, EnigmaKey, Plugboard, ReflectorId, Rotor, RotorId,
+};
+
+macro_rules! serde_as_string {
+    ($($t:ty),*) => {
+        $(
+            impl Serialize for $t {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    parse(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+serde_as_string!(
+    RotorId,
+    ReflectorId,
+    Rotor,
+    Plugboard,
+    EnigmaKey,
+    UkwD,
+    Permutation
+);
+
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::ScoredEnigmaKey, enigma::uhr::Uhr};
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de> + Display>(value: &T, json: &str) {
+        assert_eq!(serde_json::to_string(value).unwrap(), json);
+        let read: T = serde_json::from_str(json).unwrap();
+        assert_eq!(read.to_string(), value.to_string());
+    }
+
+    #[test]
+    fn parts_round_trip() {
+        round_trip(&RotorId::VIII, r#""VIII""#);
+        round_trip(&ReflectorId::BThin, r#""B-thin""#);
+        round_trip(&Rotor::new(RotorId::II, 3, 4), r#""II 4 5""#);
+
+        let ukwd: UkwD = "AC BD EF GH IK LM NO PQ RS TU VW XZ".parse().unwrap();
+        round_trip(&ukwd, r#""AC BD EF GH IK LM NO PQ RS TU VW XZ""#);
+
+        let permutation: Permutation = "(AFK)(BX)".parse().unwrap();
+        round_trip(&permutation, r#""(AFK)(BX)""#);
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let key: EnigmaKey = "II V III / 8 5 20 / 13 3 21 / AF TV".parse().unwrap();
+        round_trip(&key, r#""II V III / 8 5 20 / 13 3 21 / AF TV""#);
+
+        let m4: EnigmaKey = "Beta II IV I / 1 2 3 4 / 5 6 7 8 / AF TV".parse().unwrap();
+        round_trip(&m4, r#""Beta II IV I / 1 2 3 4 / 5 6 7 8 / AF TV""#);
+
+        let pairs = [
+            ('A', 'T'),
+            ('B', 'L'),
+            ('D', 'F'),
+            ('G', 'J'),
+            ('H', 'M'),
+            ('N', 'W'),
+            ('O', 'P'),
+            ('Q', 'Y'),
+            ('R', 'Z'),
+            ('V', 'X'),
+        ];
+        let plugboard = Plugboard::from(Uhr::new(&pairs, 13).unwrap());
+        let json = serde_json::to_string(&plugboard).unwrap();
+        let read: Plugboard = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.to_string(), plugboard.to_string());
+
+        let scored: ScoredEnigmaKey =
+            serde_json::from_str(r#"{"key":"I II III / 1 1 1 / 1 1 1","score":0.5}"#).unwrap();
+        assert_eq!(scored.score(), 0.5);
+        assert_eq!(
+            serde_json::to_string(&scored).unwrap(),
+            r#"{"key":"I II III / 1 1 1 / 1 1 1","score":0.5}"#
+        );
+    }
+
+    #[test]
+    fn invalid_text_fails() {
+        assert!(serde_json::from_str::<EnigmaKey>(r#""II V""#).is_err());
+        assert!(serde_json::from_str::<RotorId>(r#""IX""#).is_err());
+        assert!(serde_json::from_str::<EnigmaKey>("3").is_err());
+    }
+
+    #[test]
+    fn custom_parts_fail_to_deserialize() {
+        let rotor = RotorId::custom("Spare", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", &[16]).unwrap();
+        let reflector = ReflectorId::custom("Spare B", "YRUHQSLDPXNGOKMIEBFZCWVJAT").unwrap();
+
+        // Only the names are written, so there's no wiring to read them back with.
+        let json = serde_json::to_string(&rotor).unwrap();
+        assert_eq!(json, r#""Spare""#);
+        assert!(serde_json::from_str::<RotorId>(&json).is_err());
+
+        let json = serde_json::to_string(&reflector).unwrap();
+        assert_eq!(json, r#""Spare B""#);
+        assert!(serde_json::from_str::<ReflectorId>(&json).is_err());
+
+        let key = EnigmaKey::new(
+            Rotor::new(RotorId::II, 0, 0),
+            Rotor::new(RotorId::IV, 0, 0),
+            Rotor::new(rotor, 0, 0),
+            Plugboard::new(&[]),
+        );
+        let json = serde_json::to_string(&key).unwrap();
+        assert!(serde_json::from_str::<EnigmaKey>(&json).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+/// Which service's conventions to follow. They mostly differ in how numbers were written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// Numbers are spelled out digit by digit, and commas are Y.
+    Heer,
+    /// Numbers are typed on the top row of the keyboard, between a pair of Ys, and commas are YY.
+    Kriegsmarine,
+}
+
+const DIGIT_WORDS: [&str; 10] = [
+    "NULL", "EINS", "ZWO", "DREI", "VIER", "FUENF", "SEQS", "SIEBEN", "AQT", "NEUN",
+];
+
+// The letters the navy typed for the digits, indexed by digit. 1 to 9 are the top row of the keyboard,
+// QWERTZUIO, and 0 is P.
+const TOP_ROW: &[u8; 10] = b"PQWERTZUIO";
+
+// Names longer than this are more likely two ordinary words with a J in each.
+const MAX_NAME_LEN: usize = 20;
+
+/// Turns free text into uppercase letters ready for `Enigma::encrypt`.
+///
+/// * Full stops become X and colons XX. Question marks become UD, hyphens and slashes YY, and
+///   brackets KK. Commas depend on the service.
+/// * CH is shortened to Q.
+/// * Umlauts and ß are expanded, so Ä becomes AE.
+/// * Numbers are spelled out or shifted, depending on the service.
+/// * Proper names are marked in the input with square brackets, and are sent between a pair of Js,
+///   so `[Looks]` becomes JLOOKSJ.
+///
+/// Whitespace and anything else that can't be sent, such as `!`, `;` or quotes, is dropped. The
+/// Kriegsmarine sends hyphens and slashes the same as commas, so `decode` gives them back as commas.
+pub fn encode(text: &str, service: Service) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_name = false;
+
+    while let Some(c) = chars.next() {
+        if in_name {
+            match c {
+                ']' => {
+                    encoded.push('J');
+                    in_name = false;
+                }
+                c => push_letters(&mut encoded, c),
+            }
+            continue;
+        }
+
+        match c {
+            '[' => {
+                encoded.push('J');
+                in_name = true;
+            }
+            'c' | 'C' if matches!(chars.peek(), Some('h' | 'H')) => {
+                chars.next();
+                encoded.push('Q');
+            }
+            '0'..='9' => {
+                let mut digits = vec![c];
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                push_number(&mut encoded, &digits, service);
+            }
+            '.' => encoded.push('X'),
+            ':' => encoded.push_str("XX"),
+            '?' => encoded.push_str("UD"),
+            ',' => match service {
+                Service::Heer => encoded.push('Y'),
+                Service::Kriegsmarine => encoded.push_str("YY"),
+            },
+            '-' | '/' => encoded.push_str("YY"),
+            '(' | ')' => encoded.push_str("KK"),
+            c => push_letters(&mut encoded, c),
+        }
+    }
+
+    // An unclosed name still needs its closing J.
+    if in_name {
+        encoded.push('J');
+    }
+
+    encoded
+}
+
+fn push_letters(encoded: &mut String, c: char) {
+    match c {
+        'a'..='z' | 'A'..='Z' => encoded.push(c.to_ascii_uppercase()),
+        'ä' | 'Ä' => encoded.push_str("AE"),
+        'ö' | 'Ö' => encoded.push_str("OE"),
+        'ü' | 'Ü' => encoded.push_str("UE"),
+        'ß' => encoded.push_str("SS"),
+        _ => {}
+    }
+}
+
+fn push_number(encoded: &mut String, digits: &[char], service: Service) {
+    let digits = digits.iter().map(|&d| d as usize - '0' as usize);
+    match service {
+        Service::Heer => digits.for_each(|d| encoded.push_str(DIGIT_WORDS[d])),
+        Service::Kriegsmarine => {
+            encoded.push('Y');
+            encoded.extend(digits.map(|d| TOP_ROW[d] as char));
+            encoded.push('Y');
+        }
+    }
+}
+
+/// A best-effort reversal of `encode` on decrypted text, which should be uppercase ASCII letters.
+/// Anything else, including non-ASCII characters, is copied through unchanged. The output is still uppercase, and has no spaces between words.
+///
+/// The letters used for punctuation also turn up in ordinary words, so they're only restored where
+/// that's unlikely:
+///
+/// * X is a full stop, and XX a colon, only between letters or at the end. After a vowel, X is left
+///   alone if a vowel, T or P follows, as in TEXT or TAXI. So a full stop is lost in ZWOXUHR.
+/// * Q becomes CH unless U follows, so QUELLE is kept but SCHULE stays SQULE.
+/// * A name is a J, up to 20 letters with no X or Y, then another J. Two words with a J close
+///   together, such as JAHRJETZT, still come out as a name.
+/// * Y is a comma for the Heer, which also breaks up words like TYP. For the Kriegsmarine, a
+///   number between two Ys is only restored if every letter is on the top row.
+///
+/// Spelled out numbers, expanded umlauts, UD and KK are never restored.
+pub fn decode(text: &str, service: Service) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = String::with_capacity(text.len() * 2);
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (before, rest) = bytes.split_at(i);
+        let prev = before.last().copied();
+        match rest {
+            [b'J', ..] => match name_len(&rest[1..]) {
+                Some(len) => {
+                    decoded.push('[');
+                    decoded.extend(rest[1..=len].iter().map(|&c| char::from(c)));
+                    decoded.push(']');
+                    i += len + 2;
+                }
+                None => {
+                    decoded.push('J');
+                    i += 1;
+                }
+            },
+            [b'X', b'X', next @ ..] if is_full_stop(prev, next.first().copied(), true) => {
+                decoded.push_str(": ");
+                i += 2;
+            }
+            [b'X', next @ ..] if is_full_stop(prev, next.first().copied(), false) => {
+                decoded.push_str(". ");
+                i += 1;
+            }
+            [b'Q', next @ ..] if next.first() != Some(&b'U') => {
+                decoded.push_str("CH");
+                i += 1;
+            }
+            [b'Y', b'Y', ..] => {
+                match service {
+                    Service::Heer => decoded.push('-'),
+                    Service::Kriegsmarine => decoded.push_str(", "),
+                }
+                i += 2;
+            }
+            [b'Y', ..] => match service {
+                Service::Heer => {
+                    decoded.push_str(", ");
+                    i += 1;
+                }
+                Service::Kriegsmarine => match shifted_number(&rest[1..]) {
+                    Some(number) => {
+                        i += number.len() + 2;
+                        decoded.push_str(&number);
+                    }
+                    None => {
+                        decoded.push('Y');
+                        i += 1;
+                    }
+                },
+            },
+            // Every other arm only steps over ASCII, so this is always the start of a character.
+            [_, ..] => {
+                let c = text[i..].chars().next().unwrap();
+                decoded.push(c);
+                i += c.len_utf8();
+            }
+            [] => unreachable!(),
+        }
+    }
+
+    decoded.truncate(decoded.trim_end().len());
+    decoded
+}
+
+fn is_vowel(c: u8) -> bool {
+    matches!(c, b'A' | b'E' | b'I' | b'O' | b'U')
+}
+
+/// Whether an X between these letters ends a sentence, rather than being part of a word. Nothing in
+/// German has two Xs in a row, so a colon only needs letters around it.
+fn is_full_stop(prev: Option<u8>, next: Option<u8>, colon: bool) -> bool {
+    let (prev, next) = match (prev, next) {
+        (Some(prev), next) if prev.is_ascii_uppercase() => (prev, next),
+        _ => return false,
+    };
+
+    match next {
+        None => true,
+        Some(next) if !next.is_ascii_uppercase() => false,
+        Some(next) => colon || !is_vowel(prev) || !(is_vowel(next) || next == b'T' || next == b'P'),
+    }
+}
+
+/// The length of the name up to the closing J, if there's one close enough.
+fn name_len(text: &[u8]) -> Option<usize> {
+    let len = text
+        .iter()
+        .take(MAX_NAME_LEN + 1)
+        .position(|&c| c == b'J')?;
+    let name = &text[..len];
+    let clear = !name.is_empty()
+        && name
+            .iter()
+            .all(|&c| c.is_ascii_uppercase() && c != b'X' && c != b'Y');
+
+    clear.then_some(len)
+}
+
+/// Reads the digits up to the closing Y, if they're all on the top row.
+fn shifted_number(text: &[u8]) -> Option<String> {
+    let len = text.iter().position(|&c| c == b'Y')?;
+    text[..len]
+        .iter()
+        .map(|c| {
+            TOP_ROW
+                .iter()
+                .position(|t| t == c)
+                .map(|d| (b'0' + d as u8) as char)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heer_round_trip() {
+        let text = "Angriff bei [Looks] um 12 Uhr. Bericht folgt, Ende.";
+        let encoded = encode(text, Service::Heer);
+        assert_eq!(encoded, "ANGRIFFBEIJLOOKSJUMEINSZWOUHRXBERIQTFOLGTYENDEX");
+        assert_eq!(
+            decode(&encoded, Service::Heer),
+            "ANGRIFFBEI[LOOKS]UMEINSZWOUHR. BERICHTFOLGT, ENDE."
+        );
+    }
+
+    #[test]
+    fn kriegsmarine_round_trip() {
+        let text = "Feindlicher Verband in Quadrat 1234: Kurs 270, Ende.";
+        let encoded = encode(text, Service::Kriegsmarine);
+        assert_eq!(
+            encoded,
+            "FEINDLIQERVERBANDINQUADRATYQWERYXXKURSYWUPYYYENDEX"
+        );
+        assert_eq!(
+            decode(&encoded, Service::Kriegsmarine),
+            "FEINDLICHERVERBANDINQUADRAT1234: KURS270, ENDE."
+        );
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(
+            encode("Süd-West (Nord)?", Service::Heer),
+            "SUEDYYWESTKKNORDKKUD"
+        );
+        assert_eq!(
+            encode("Süd-West (Nord)?", Service::Kriegsmarine),
+            "SUEDYYWESTKKNORDKKUD"
+        );
+        assert_eq!(encode("Halt! \"Ja\";", Service::Heer), "HALTJA");
+    }
+
+    #[test]
+    fn ordinary_words_are_kept() {
+        for service in [Service::Heer, Service::Kriegsmarine] {
+            assert_eq!(decode("TEXTTAXIJAHR", service), "TEXTTAXIJAHR");
+            assert_eq!(decode("QUELLE", service), "QUELLE");
+            assert_eq!(decode("NRX12", service), "NRX12");
+        }
+    }
+
+    #[test]
+    fn non_ascii_is_copied() {
+        assert_eq!(decode("ÄBC", Service::Heer), "ÄBC");
+        assert_eq!(decode("GRÜßEXJÖJ", Service::Heer), "GRÜßE. JÖJ");
+        assert_eq!(
+            decode("JMÜLLERJXQ€", Service::Kriegsmarine),
+            "JMÜLLERJ. CH€"
+        );
+        assert_eq!(decode("JHANSJÄ", Service::Heer), "[HANS]Ä");
+    }
+}